@@ -1,3 +1,66 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::assembler::{self, AsmError, Program, DATA_BASE, KTEXT_BASE, TEXT_BASE};
+use crate::bitmap::{self, BitmapConfig, DISPLAY_SIZES, UNIT_SIZES};
+use crate::cache::{Cache, Replacement, WritePolicy};
+use crate::cp0::{self, Cp0};
+use crate::cp1::{Cp1, FP_REGISTER_NAMES};
+use crate::cpu::{Cpu, Register};
+use crate::disasm;
+use crate::elf;
+use crate::image::{self, ImageFormat};
+use crate::isa::{Field, Format, REGISTER_NAMES};
+use crate::machine::{Machine, RuntimeError, Status};
+use crate::memory::Endianness;
+use crate::mmio::{
+    LED_ADDRESS, MMIO_BASE, SEGMENT_ADDRESS, SEGMENT_DIGITS, SEGMENT_DP, SWITCH_ADDRESS,
+};
+use crate::pipeline::{Pipeline, Slot, STAGE_NAMES};
+use crate::syscall::Console;
+
+/// How long a register stays highlighted after an instruction writes it
+const FLASH_SECONDS: f64 = 0.6;
+
+/// Program shown in the editor the first time the app starts
+const DEFAULT_SOURCE: &str = "# Count down from 5 to 0 in $t0, printing each value
+        .data
+sep:    .asciiz \" \"
+
+        .text
+main:   li   $t0, 5
+loop:   addi $t0, $t0, -1
+        move $a0, $t0
+        li   $v0, 1
+        syscall
+        la   $a0, sep
+        li   $v0, 4
+        syscall
+        bgt  $t0, $zero, loop
+done:   j    done
+";
+
+/// Help shown below the LED grid, grouped by topic
+const INSTRUCTIONS: &str = "\
+• Each row represents a 32-bit memory word, grouped into bytes in address order; click any LED to toggle it (red = 1, gray = 0)
+• Rows show a window of the 32-bit address space; use Go to or ⏴/⏵ to move it and Add/Remove Row to change how many are shown
+• Field colouring tints each LED by the instruction field it belongs to
+• Write MIPS assembly on the left and press Assemble; .text loads at 0x00400000, .data at 0x10010000
+• Pseudo-instructions such as li, la and blt expand to several words, each marked with its source
+• Step executes the instruction at PC; Run steps at the selected clock rate
+• Click the gutter left of an address to toggle a breakpoint; Run pauses before it
+• Registers on the right flash when the last instruction wrote them; $zero is read-only
+• The FPU rows tint sign, exponent and fraction bits and show the value as a float, or as doubles for register pairs
+• syscall output appears in the console below; type input there for read services
+• Exceptions jump to a handler at 0x80000180 written after .ktext, or stop the run if there is none
+• Pipelined mode steps one clock cycle at a time; the pipeline panel shows stalls, bubbles and flushes
+• The cache panel configures the I- and D-caches; rows they hold are marked left of the address
+• Drop an ELF32 MIPS executable onto the window to load it, or an assembly file to open it; rows show its symbols
+• File→Import/Export moves memory as Intel HEX, raw binary, Logisim v2.0 raw or $readmemh text
+• The I/O board shows the LEDs a program lights with sw to 0xFFFF0010 and the switches it reads with lw from 0xFFFF0014
+• Bytes stored to 0xFFFF0018–0xFFFF001F drive its seven-segment digits, as raw a–g/dp segments or decoded hex
+• The terminal panel is a MARS-style keyboard and display at 0xFFFF0000–0xFFFF000C; polling and interrupts both work
+• The bitmap display shows memory from its base address as 0x00RRGGBB pixels, one word per unit, row by row";
+
 /// LED Memory Display Component for MIPS Emulator
 #[derive(Clone)]
 pub struct MemoryRow {
@@ -31,150 +94,1131 @@ impl MemoryRow {
     }
 }
 
+/// Which cache the cache panel shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+enum CacheView {
+    Instruction,
+    Data,
+}
+
+/// Which way the File→Import/Export window moves memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ImageDialog {
+    Import,
+    Export,
+}
+
+/// Console panel contents: program output and lines typed by the user
+#[derive(Default)]
+struct ConsoleBuffer {
+    output: String,
+    input: VecDeque<String>,
+}
+
+impl Console for ConsoleBuffer {
+    fn print(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+}
+
 /// MIPS Emulator App with LED Memory Display
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct TemplateApp {
-    #[serde(skip)] // Don't serialize the memory rows for now
-    memory_rows: Vec<MemoryRow>,
-    
+    machine: Machine,
+    #[serde(skip)]
+    console: ConsoleBuffer,
+    #[serde(skip)]
+    console_input: String,
+    /// Keys typed into the MMIO terminal, sent to the receiver as they arrive
+    #[serde(skip)]
+    terminal_keys: String,
+
+    // Execution state
+    #[serde(skip)]
+    running: bool,
+    #[serde(skip)]
+    pending_steps: f64,
+    /// Let the first instruction of a run execute even if it has a breakpoint
+    #[serde(skip)]
+    resume_past_breakpoint: bool,
+    breakpoints: BTreeSet<u32>,
+    /// Step through the five-stage pipeline one clock cycle at a time
+    pipelined: bool,
+    pipeline: Pipeline,
+    #[serde(skip)]
+    status: String,
+    /// Time of the last executed instruction, for flashing written registers
+    #[serde(skip)]
+    last_step_time: f64,
+    clock_hz: f32,
+
+    // Assembly source shown in the editor panel
+    source: String,
+    /// Error from the last Assemble, underlined in the editor until the source changes
+    #[serde(skip)]
+    asm_error: Option<AsmError>,
+
     // UI state
+    /// First address shown in the LED grid
+    view_base: u32,
+    #[serde(skip)]
+    view_input: String,
     num_rows: usize,
     led_size: f32,
+    field_colouring: bool,
+    show_cache: bool,
+    /// Show the memory-mapped LEDs, switches and seven-segment digits above the grid
+    show_board: bool,
+    show_terminal: bool,
+    show_bitmap: bool,
+    bitmap: BitmapConfig,
+    /// Pixels of the bitmap display, refilled from memory every frame it is shown
+    #[serde(skip)]
+    bitmap_texture: Option<egui::TextureHandle>,
+    /// Decode FPU register pairs as doubles instead of singles
+    fp_doubles: bool,
+    cache_view: CacheView,
+
+    // Memory image import/export window
+    #[serde(skip)]
+    image_dialog: Option<ImageDialog>,
+    image_format: ImageFormat,
+    /// Load address for imports and start address for exports
+    image_address: String,
+    image_words: u32,
+    image_path: String,
 }
 
 impl Default for TemplateApp {
     fn default() -> Self {
-        let mut app = Self {
-            memory_rows: Vec::new(),
+        Self {
+            machine: Machine::new(),
+            console: ConsoleBuffer::default(),
+            console_input: String::new(),
+            terminal_keys: String::new(),
+            running: false,
+            pending_steps: 0.0,
+            resume_past_breakpoint: false,
+            breakpoints: BTreeSet::new(),
+            pipelined: false,
+            pipeline: Pipeline::default(),
+            status: String::new(),
+            last_step_time: f64::NEG_INFINITY,
+            clock_hz: 4.0,
+            source: DEFAULT_SOURCE.to_owned(),
+            asm_error: None,
+            view_base: TEXT_BASE,
+            view_input: String::new(),
             num_rows: 4, // Default to 8 rows
             led_size: 12.0,
-        };
-        
-        // Initialize with some default memory rows
-        app.initialize_memory_rows();
-        app
+            field_colouring: false,
+            show_cache: false,
+            show_board: false,
+            show_terminal: false,
+            show_bitmap: false,
+            bitmap: BitmapConfig::default(),
+            bitmap_texture: None,
+            fp_doubles: false,
+            cache_view: CacheView::Data,
+            image_dialog: None,
+            image_format: ImageFormat::default(),
+            image_address: format!("{TEXT_BASE:08X}"),
+            image_words: 256,
+            image_path: String::new(),
+        }
     }
 }
 
 impl TemplateApp {
-    /// Initialize memory rows starting from address 0x00000000
-    fn initialize_memory_rows(&mut self) {
-        self.memory_rows.clear();
-        for i in 0..self.num_rows {
-            let address = (i as u32) * 4; // Word-aligned addresses
-            let data = 0; // Initialize with zeros
-            self.memory_rows.push(MemoryRow::new(address, data));
-        }
-    }
-
     /// Add a new memory row
     pub fn add_memory_row(&mut self) {
-        let address = (self.memory_rows.len() as u32) * 4;
-        self.memory_rows.push(MemoryRow::new(address, 0));
-        self.num_rows = self.memory_rows.len();
+        self.num_rows += 1;
     }
 
     /// Remove the last memory row
     pub fn remove_memory_row(&mut self) {
-        if !self.memory_rows.is_empty() {
-            self.memory_rows.pop();
-            self.num_rows = self.memory_rows.len();
+        if self.num_rows > 0 {
+            self.num_rows -= 1;
         }
     }
 
+    /// Address of the `index`th row of the LED grid
+    fn row_address(&self, index: usize) -> u32 {
+        self.view_base.wrapping_add(index as u32 * 4)
+    }
+
+    /// Move the LED grid so its first row shows `address`
+    pub fn set_view_base(&mut self, address: u32) {
+        self.view_base = address & !3;
+    }
+
     /// Set data for a specific memory address
     pub fn set_memory_data(&mut self, address: u32, data: u32) {
-        if let Some(row) = self.memory_rows.iter_mut().find(|r| r.address == address) {
-            row.data = data;
-        }
+        self.machine.memory.write_word(address, data);
     }
 
     /// Get data from a specific memory address
-    pub fn get_memory_data(&self, address: u32) -> Option<u32> {
-        self.memory_rows.iter().find(|r| r.address == address).map(|r| r.data)
+    pub fn get_memory_data(&self, address: u32) -> u32 {
+        self.machine.memory.read_word(address)
     }
 
     /// Load data from an array into memory starting at address 0x00000000
     pub fn load_memory_from_array(&mut self, data: &[u32]) {
         // Ensure we have enough rows
-        while self.memory_rows.len() < data.len() {
-            self.add_memory_row();
-        }
-        
+        self.num_rows = self.num_rows.max(data.len());
+
         // Load the data
-        for (i, &value) in data.iter().enumerate() {
-            if i < self.memory_rows.len() {
-                self.memory_rows[i].data = value;
-            }
-        }
+        self.machine.memory.load_words(0, data);
     }
 
     /// Clear all memory (set all bits to 0)
     pub fn clear_memory(&mut self) {
-        for row in &mut self.memory_rows {
-            row.data = 0;
-        }
+        self.machine.memory.clear();
+        self.machine.symbols.clear();
+        self.machine.pseudo_sources.clear();
     }
 
-    /// Set a test pattern (alternating bits)
+    /// Set a test pattern (alternating bits) in the rows currently shown
     pub fn set_test_pattern(&mut self) {
-        for (i, row) in self.memory_rows.iter_mut().enumerate() {
+        for i in 0..self.num_rows {
             // Alternate between 0xAAAAAAAA and 0x55555555
-            row.data = if i % 2 == 0 { 0xAAAAAAAA } else { 0x55555555 };
+            let data = if i % 2 == 0 { 0xAAAAAAAA } else { 0x55555555 };
+            self.machine.memory.write_word(self.row_address(i), data);
+        }
+    }
+
+    /// Execute the instruction at PC, or advance the pipeline one cycle in
+    /// pipelined mode, servicing syscalls through the console panel
+    pub fn step(&mut self) -> Result<Status, RuntimeError> {
+        if self.pipelined {
+            self.pipeline.cycle(&mut self.machine, &mut self.console)
+        } else {
+            self.machine.step(&mut self.console)
+        }
+    }
+
+    /// Switch between single-cycle and pipelined execution, refilling the pipeline from PC
+    pub fn set_pipelined(&mut self, pipelined: bool) {
+        self.pipelined = pipelined;
+        self.pipeline.reset(self.machine.cpu.pc);
+    }
+
+    /// Access the CPU state
+    pub fn cpu(&self) -> &Cpu {
+        &self.machine.cpu
+    }
+
+    /// Execute one instruction, stopping the run and reporting any error
+    fn step_and_report(&mut self, now: f64) -> Option<Status> {
+        let pc = self.machine.cpu.pc;
+        self.last_step_time = now;
+        match self.step() {
+            Ok(Status::Running) => {
+                self.status.clear();
+                Some(Status::Running)
+            }
+            Ok(Status::WaitingForInput) => {
+                self.status = "Waiting for console input".to_owned();
+                Some(Status::WaitingForInput)
+            }
+            Ok(Status::Exception(e)) => {
+                self.status = format!(
+                    "Exception at 0x{pc:08X}: {}, entering the handler",
+                    cp0::exc_code_name(e.code())
+                );
+                Some(Status::Exception(e))
+            }
+            Ok(Status::Exited(code)) => {
+                self.running = false;
+                self.status = format!("Program exited with code {code}");
+                Some(Status::Exited(code))
+            }
+            Err(e) => {
+                self.running = false;
+                self.status = format!("Stopped at 0x{pc:08X}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Add or remove a breakpoint on the instruction at `address`
+    pub fn toggle_breakpoint(&mut self, address: u32) {
+        if !self.breakpoints.remove(&address) {
+            self.breakpoints.insert(address);
+        }
+    }
+
+    /// Reset the CPU without touching memory
+    pub fn reset_cpu(&mut self) {
+        self.machine.reset();
+        self.pipeline.reset(self.machine.cpu.pc);
+        self.running = false;
+        self.pending_steps = 0.0;
+        self.status.clear();
+    }
+
+    /// Start running from PC, past a breakpoint on it
+    fn start_running(&mut self) {
+        self.running = true;
+        self.pending_steps = 0.0;
+        self.resume_past_breakpoint = true;
+        self.status.clear();
+    }
+
+    /// Advance the running machine by however many cycles the clock rate allows this frame
+    fn run_frame(&mut self, ctx: &egui::Context) {
+        if !self.running {
+            return;
+        }
+
+        let (dt, now) = ctx.input(|i| (i.stable_dt as f64, i.time));
+        // Cap the backlog so a stalled frame doesn't turn into a burst of thousands of steps
+        self.pending_steps = (self.pending_steps + dt * self.clock_hz as f64).min(10_000.0);
+        while self.running && self.pending_steps >= 1.0 {
+            let pc = self.machine.cpu.pc;
+            if self.breakpoints.contains(&pc) && !self.resume_past_breakpoint {
+                self.running = false;
+                self.status = format!("Breakpoint at 0x{pc:08X}");
+                break;
+            }
+            self.pending_steps -= 1.0;
+            let status = self.step_and_report(now);
+            // A pipeline may need several cycles before the instruction at PC executes
+            if self.machine.cpu.pc != pc {
+                self.resume_past_breakpoint = false;
+            }
+            if status == Some(Status::WaitingForInput) {
+                // Keep running, but don't spin on the syscall until input arrives
+                self.pending_steps = 0.0;
+                break;
+            }
+        }
+
+        ctx.request_repaint();
+    }
+
+    /// Assemble the editor contents and load its text and data segments
+    pub fn assemble_source(&mut self) {
+        self.asm_error = None;
+        match assembler::assemble(&self.source, self.machine.memory.endianness()) {
+            Ok(program) => {
+                self.load_program(&program);
+                self.status = format!(
+                    "Assembled {} instruction(s), entry at 0x{:08X}",
+                    program.instruction_count(),
+                    program.entry
+                );
+            }
+            Err(e) => {
+                self.status = format!("Assembly failed: {e}");
+                self.asm_error = Some(e);
+            }
+        }
+    }
+
+    /// Replace memory with `program`, reset and show its entry point
+    fn load_program(&mut self, program: &Program) {
+        self.machine.load_program(program);
+        self.reset_cpu();
+        self.set_view_base(program.entry);
+    }
+
+    /// Load a file's contents: an ELF executable is loaded into memory, a
+    /// memory image is written over memory from the first row shown, and
+    /// anything else is opened in the editor as assembly and assembled
+    pub fn load_file(&mut self, name: &str, bytes: &[u8]) {
+        if elf::is_elf(bytes) {
+            match elf::read_elf(bytes) {
+                Ok(program) => {
+                    self.load_program(&program);
+                    self.status = format!(
+                        "Loaded {name}: {} segment(s), {} symbol(s), entry at 0x{:08X}, branch delay slots on",
+                        program.segments.len(),
+                        program.labels.len(),
+                        program.entry
+                    );
+                }
+                Err(e) => self.status = format!("Cannot load {name}: {e}"),
+            }
+            return;
+        }
+        let endianness = self.machine.memory.endianness();
+        if let Some(format) = ImageFormat::detect(name, bytes, endianness) {
+            self.import_image(name, bytes, format, self.view_base);
+            return;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => {
+                self.source = text.to_owned();
+                self.assemble_source();
+            }
+            Err(_) => {
+                self.status =
+                    format!("Cannot load {name}: not an ELF executable or assembly source");
+            }
+        }
+    }
+
+    /// Write a memory image over memory without resetting the CPU; word
+    /// formats start at `base`
+    pub fn import_image(&mut self, name: &str, bytes: &[u8], format: ImageFormat, base: u32) {
+        match image::import_image(&mut self.machine.memory, format, base, bytes) {
+            Ok(imported) => {
+                self.set_view_base(imported.lowest);
+                self.status = format!(
+                    "Imported {} byte(s) of {} from {name} at 0x{:08X}",
+                    imported.bytes,
+                    format.name(),
+                    imported.lowest
+                );
+            }
+            Err(e) => self.status = format!("Cannot import {name}: {e}"),
+        }
+    }
+
+    /// Load every file dropped onto the window this frame
+    ///
+    /// While the Import window is open its format and address are used
+    /// instead of guessing from the file.
+    fn load_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        for file in dropped {
+            // Native drops give a path, web drops give the contents
+            let name = match &file.path {
+                Some(path) => path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                None => file.name.clone(),
+            };
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => bytes.to_vec(),
+                (None, Some(path)) => match std::fs::read(path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        self.status = format!("Cannot read {name}: {e}");
+                        continue;
+                    }
+                },
+                (None, None) => continue,
+            };
+            if self.image_dialog == Some(ImageDialog::Import) {
+                match parse_address(&self.image_address) {
+                    Some(base) => self.import_image(&name, &bytes, self.image_format, base),
+                    None => self.status = "Invalid load address".to_owned(),
+                }
+            } else {
+                self.load_file(&name, &bytes);
+            }
         }
     }
 
+    /// Draw the GPRs, PC, HI, LO, CP0 and the FPU as LED rows
+    fn draw_register_panel(&mut self, ui: &mut egui::Ui) {
+        let now = ui.input(|i| i.time);
+        let flash = (1.0 - (now - self.last_step_time) / FLASH_SECONDS).clamp(0.0, 1.0) as f32;
+        if flash > 0.0 {
+            ui.ctx().request_repaint();
+        }
+        let led_size = (self.led_size * 0.75).max(6.0);
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, name) in REGISTER_NAMES.iter().enumerate() {
+                let written = self.machine.cpu.was_written(Register::Gpr(index));
+                let mut value = self.machine.cpu.regs[index];
+                // $zero is hard-wired, so its LEDs are not clickable
+                draw_register_row(ui, name, &mut value, led_size, index != 0, written, flash);
+                self.machine.cpu.regs[index] = value;
+            }
+            ui.separator();
+            draw_register_row(ui, "pc", &mut self.machine.cpu.pc, led_size, true, false, flash);
+            let hi_written = self.machine.cpu.was_written(Register::Hi);
+            draw_register_row(ui, "hi", &mut self.machine.cpu.hi, led_size, true, hi_written, flash);
+            let lo_written = self.machine.cpu.was_written(Register::Lo);
+            draw_register_row(ui, "lo", &mut self.machine.cpu.lo, led_size, true, lo_written, flash);
+            ui.separator();
+            let cp0 = &mut self.machine.cpu.cp0;
+            for (name, value) in [
+                ("vaddr", &mut cp0.bad_vaddr),
+                ("count", &mut cp0.count),
+                ("cmp", &mut cp0.compare),
+                ("sr", &mut cp0.status),
+                ("cause", &mut cp0.cause),
+                ("epc", &mut cp0.epc),
+            ] {
+                draw_register_row(ui, name, value, led_size, true, false, flash);
+            }
+            draw_cp0_summary(ui, cp0);
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.strong("FPU");
+                ui.checkbox(&mut self.fp_doubles, "Doubles").on_hover_text(
+                    "Decode even/odd register pairs as doubles; the odd register holds the sign and exponent",
+                );
+            });
+            for index in 0..32 {
+                let written = self.machine.cpu.was_written(Register::Fpr(index));
+                draw_fp_register_row(
+                    ui,
+                    &mut self.machine.cpu.cp1,
+                    index,
+                    self.fp_doubles,
+                    led_size,
+                    written,
+                    flash,
+                );
+            }
+            let cp1 = &mut self.machine.cpu.cp1;
+            draw_register_row(ui, "fcsr", &mut cp1.fcsr, led_size, true, false, flash);
+            ui.horizontal(|ui| {
+                ui.monospace("Condition flags 7..0");
+                let flags = (0..8).fold(0, |flags, cc| flags | (cp1.condition(cc) as u32) << cc);
+                draw_bit_leds(ui, flags, 8, led_size, egui::Color32::from_rgb(60, 180, 90));
+            });
+        });
+    }
+
+    /// Draw the stage diagram for recent cycles and the pipeline registers
+    fn draw_pipeline_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Pipeline");
+            ui.checkbox(&mut self.pipeline.forwarding, "Forwarding")
+                .on_hover_text(
+                "Without forwarding, any instruction that reads a result still in EX or MEM stalls",
+            );
+            ui.separator();
+            let pipeline = &self.pipeline;
+            let cpi = if pipeline.retired == 0 {
+                0.0
+            } else {
+                pipeline.cycles as f64 / pipeline.retired as f64
+            };
+            ui.label(format!(
+                "Cycle {}, {} retired, {} stall(s), {} flush(es), CPI {cpi:.2}",
+                pipeline.cycles, pipeline.retired, pipeline.stalls, pipeline.flushes
+            ));
+        });
+        let pipeline = &self.pipeline;
+
+        ui.horizontal_top(|ui| {
+            // One row per recent cycle, newest at the bottom
+            egui::Grid::new("pipeline_diagram")
+                .striped(true)
+                .min_col_width(110.0)
+                .show(ui, |ui| {
+                    ui.strong("Cycle");
+                    for name in STAGE_NAMES {
+                        ui.strong(name);
+                    }
+                    ui.end_row();
+
+                    let first = pipeline.cycles + 1 - pipeline.history().count() as u64;
+                    for (cycle, slots) in (first..).zip(pipeline.history()) {
+                        ui.monospace(cycle.to_string());
+                        for slot in slots {
+                            draw_slot(ui, slot);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+            ui.separator();
+
+            ui.vertical(|ui| {
+                let led_size = (self.led_size * 0.75).max(6.0);
+                for (name, mut value) in pipeline.registers() {
+                    draw_register_row(
+                        ui,
+                        &format!("{name:>11}"),
+                        &mut value,
+                        led_size,
+                        false,
+                        false,
+                        0.0,
+                    );
+                }
+            });
+        });
+    }
+
+    /// Draw the selected cache's settings, counters and lines
+    fn draw_cache_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Cache");
+            ui.selectable_value(&mut self.cache_view, CacheView::Instruction, "I-cache");
+            ui.selectable_value(&mut self.cache_view, CacheView::Data, "D-cache");
+        });
+        ui.separator();
+
+        let is_data = self.cache_view == CacheView::Data;
+        let cache = if is_data {
+            &mut self.machine.dcache
+        } else {
+            &mut self.machine.icache
+        };
+        let mut config = cache.config;
+        ui.checkbox(&mut config.enabled, "Enabled");
+        egui::Grid::new("cache_config")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Size:");
+                egui::ComboBox::from_id_salt("cache_size")
+                    .selected_text(format!("{} B", config.size))
+                    .show_ui(ui, |ui| {
+                        for size in [64, 128, 256, 512, 1024, 2048, 4096] {
+                            ui.selectable_value(&mut config.size, size, format!("{size} B"));
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Block:");
+                egui::ComboBox::from_id_salt("cache_block")
+                    .selected_text(format!("{} B", config.block_size))
+                    .show_ui(ui, |ui| {
+                        for block in [4, 8, 16, 32, 64] {
+                            ui.selectable_value(
+                                &mut config.block_size,
+                                block,
+                                format!("{block} B"),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Associativity:");
+                let blocks = config.blocks();
+                let describe = |ways: u32| match ways {
+                    1 => "Direct-mapped".to_owned(),
+                    ways if ways >= blocks => "Fully associative".to_owned(),
+                    ways => format!("{ways}-way"),
+                };
+                egui::ComboBox::from_id_salt("cache_ways")
+                    .selected_text(describe(config.ways()))
+                    .show_ui(ui, |ui| {
+                        for ways in [1, 2, 4, 8].into_iter().filter(|&w| w < blocks) {
+                            ui.selectable_value(&mut config.associativity, ways, describe(ways));
+                        }
+                        ui.selectable_value(&mut config.associativity, blocks, describe(blocks));
+                    });
+                ui.end_row();
+
+                ui.label("Replacement:");
+                egui::ComboBox::from_id_salt("cache_replacement")
+                    .selected_text(config.replacement.name())
+                    .show_ui(ui, |ui| {
+                        for replacement in Replacement::ALL {
+                            ui.selectable_value(
+                                &mut config.replacement,
+                                replacement,
+                                replacement.name(),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                // Instruction fetches never write
+                if is_data {
+                    ui.label("Writes:");
+                    egui::ComboBox::from_id_salt("cache_write_policy")
+                        .selected_text(config.write_policy.name())
+                        .show_ui(ui, |ui| {
+                            for policy in WritePolicy::ALL {
+                                ui.selectable_value(
+                                    &mut config.write_policy,
+                                    policy,
+                                    policy.name(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                }
+            });
+        if config != cache.config {
+            cache.config = config;
+            cache.reset();
+        }
+        if ui
+            .button("Flush")
+            .on_hover_text("Invalidate every line and clear the counters")
+            .clicked()
+        {
+            cache.reset();
+        }
+        ui.separator();
+
+        ui.label(format!(
+            "Hits: {}  Misses: {}  Hit rate: {:.1}%",
+            cache.hits,
+            cache.misses,
+            cache.hit_rate() * 100.0
+        ));
+        if is_data {
+            ui.label(format!("Memory writes: {}", cache.memory_writes));
+        }
+        ui.label(format!(
+            "{} set(s) × {} way(s); tag {} / index {} / offset {} bits",
+            config.sets(),
+            config.ways(),
+            config.tag_bits(),
+            config.index_bits(),
+            config.offset_bits()
+        ));
+        ui.separator();
+
+        let led_size = (self.led_size * 0.75).max(6.0);
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            draw_cache_lines(ui, cache, led_size);
+        });
+    }
+
+    /// Draw program output and the input line used by read syscalls
+    fn draw_console_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Console");
+            if ui.button("Clear").clicked() {
+                self.console.output.clear();
+            }
+        });
+        egui::ScrollArea::vertical()
+            .max_height(120.0)
+            .stick_to_bottom(true)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                ui.monospace(&self.console.output);
+            });
+        ui.horizontal(|ui| {
+            ui.label("Input:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.console_input)
+                    .code_editor()
+                    .desired_width(f32::INFINITY),
+            );
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut self.console_input);
+                // Echo the input like a terminal would
+                self.console.output.push_str(&line);
+                self.console.output.push('\n');
+                self.console.input.push_back(line);
+                response.request_focus();
+            }
+        });
+    }
+
+    /// Draw the memory-mapped terminal: what the transmitter sent and a line
+    /// whose keys go straight to the receiver
+    fn draw_terminal_panel(&mut self, ui: &mut egui::Ui) {
+        let uart = &mut self.machine.devices.uart;
+        ui.horizontal(|ui| {
+            ui.heading("Terminal");
+            if ui.button("Clear").clicked() {
+                uart.output.clear();
+            }
+            ui.label(format!("MMIO at 0x{MMIO_BASE:08X}"));
+            if !uart.input.is_empty() {
+                ui.label(format!("{} key(s) waiting", uart.input.len()));
+            }
+        });
+        egui::ScrollArea::vertical()
+            .max_height(120.0)
+            .stick_to_bottom(true)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                ui.monospace(&uart.output);
+            });
+        ui.horizontal(|ui| {
+            ui.label("Keys:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.terminal_keys)
+                    .code_editor()
+                    .hint_text("type here while the program runs")
+                    .desired_width(f32::INFINITY),
+            );
+            if response.changed() {
+                uart.type_text(&std::mem::take(&mut self.terminal_keys));
+            }
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                uart.type_text("\n");
+                response.request_focus();
+            }
+        });
+    }
+
+    /// Draw the bitmap display's settings and the memory region it shows
+    fn draw_bitmap_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Bitmap display");
+        ui.separator();
+
+        let config = &mut self.bitmap;
+        egui::Grid::new("bitmap_config")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Unit width:");
+                size_choice(ui, "unit_width", &mut config.unit_width, &UNIT_SIZES);
+                ui.end_row();
+                ui.label("Unit height:");
+                size_choice(ui, "unit_height", &mut config.unit_height, &UNIT_SIZES);
+                ui.end_row();
+                ui.label("Display width:");
+                size_choice(
+                    ui,
+                    "display_width",
+                    &mut config.display_width,
+                    &DISPLAY_SIZES,
+                );
+                ui.end_row();
+                ui.label("Display height:");
+                size_choice(
+                    ui,
+                    "display_height",
+                    &mut config.display_height,
+                    &DISPLAY_SIZES,
+                );
+                ui.end_row();
+
+                ui.label("Base address:");
+                egui::ComboBox::from_id_salt("bitmap_base")
+                    .selected_text(describe_base(config.base))
+                    .show_ui(ui, |ui| {
+                        for (base, _) in bitmap::BASE_ADDRESSES {
+                            ui.selectable_value(&mut config.base, base, describe_base(base));
+                        }
+                    });
+                ui.end_row();
+            });
+        ui.label(format!(
+            "{} × {} units at 0x{:08X} – 0x{:08X}",
+            config.columns(),
+            config.rows(),
+            config.base,
+            config.end()
+        ));
+        ui.separator();
+
+        let size = [config.columns() as usize, config.rows() as usize];
+        let rgb: Vec<u8> = config.pixels(&self.machine.memory).concat();
+        let image = egui::ColorImage::from_rgb(size, &rgb);
+        let texture = match &mut self.bitmap_texture {
+            Some(texture) => {
+                texture.set(image, egui::TextureOptions::NEAREST);
+                texture
+            }
+            None => self.bitmap_texture.insert(ui.ctx().load_texture(
+                "bitmap_display",
+                image,
+                egui::TextureOptions::NEAREST,
+            )),
+        };
+        let display = egui::vec2(config.display_width as f32, config.display_height as f32);
+        egui::ScrollArea::both().show(ui, |ui| {
+            ui.image((texture.id(), display));
+        });
+    }
+
+    /// Draw the File→Import/Export window while it is open
+    ///
+    /// Native builds read and write the file named in the window; on the web
+    /// images are dropped onto the window and text formats copied out.
+    fn draw_image_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = self.image_dialog else {
+            return;
+        };
+        let is_web = cfg!(target_arch = "wasm32");
+        let title = match dialog {
+            ImageDialog::Import => "Import memory image",
+            ImageDialog::Export => "Export memory image",
+        };
+        let mut open = true;
+        egui::Window::new(title)
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let absolute = dialog == ImageDialog::Import && self.image_format.is_absolute();
+                egui::Grid::new("image_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Format:");
+                    egui::ComboBox::from_id_salt("image_format")
+                        .selected_text(self.image_format.name())
+                        .show_ui(ui, |ui| {
+                            for format in ImageFormat::ALL {
+                                ui.selectable_value(&mut self.image_format, format, format.name());
+                            }
+                        });
+                    ui.end_row();
+                    ui.label(match dialog {
+                        ImageDialog::Import => "Load at:",
+                        ImageDialog::Export => "Start:",
+                    });
+                    ui.add_enabled(
+                        !absolute,
+                        egui::TextEdit::singleline(&mut self.image_address)
+                            .code_editor()
+                            .desired_width(80.0),
+                    );
+                    ui.end_row();
+                    if dialog == ImageDialog::Export {
+                        ui.label("Words:");
+                        ui.add(egui::DragValue::new(&mut self.image_words).range(1..=1 << 24));
+                        ui.end_row();
+                    }
+                    if !is_web {
+                        ui.label("File:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.image_path)
+                                .hint_text(format!("memory.{}", self.image_format.extension())),
+                        );
+                        ui.end_row();
+                    }
+                });
+                if absolute {
+                    ui.label("Intel HEX records carry their own addresses");
+                }
+
+                let path = match self.image_path.trim() {
+                    "" => format!("memory.{}", self.image_format.extension()),
+                    path => path.to_owned(),
+                };
+                let address = parse_address(&self.image_address);
+                ui.horizontal(|ui| match dialog {
+                    ImageDialog::Import => {
+                        if !is_web && ui.button("Import").clicked() {
+                            match (address, std::fs::read(&path)) {
+                                (Some(base), Ok(bytes)) => {
+                                    self.import_image(&path, &bytes, self.image_format, base);
+                                }
+                                (None, _) => self.status = "Invalid load address".to_owned(),
+                                (_, Err(e)) => self.status = format!("Cannot read {path}: {e}"),
+                            }
+                        }
+                        ui.label("or drop a file onto the window");
+                    }
+                    ImageDialog::Export => {
+                        let Some(start) = address else {
+                            ui.colored_label(ui.visuals().error_fg_color, "Invalid start address");
+                            return;
+                        };
+                        let export = || {
+                            image::export_image(
+                                &self.machine.memory,
+                                self.image_format,
+                                start,
+                                self.image_words,
+                            )
+                        };
+                        if !is_web && ui.button("Save").clicked() {
+                            self.status = match std::fs::write(&path, export()) {
+                                Ok(()) => format!(
+                                    "Exported {} word(s) from 0x{start:08X} to {path}",
+                                    self.image_words
+                                ),
+                                Err(e) => format!("Cannot write {path}: {e}"),
+                            };
+                        }
+                        if self.image_format.is_text() && ui.button("Copy").clicked() {
+                            let text = String::from_utf8_lossy(&export()).into_owned();
+                            ui.ctx().copy_text(text);
+                            self.status = format!(
+                                "Copied {} word(s) from 0x{start:08X} as {}",
+                                self.image_words,
+                                self.image_format.name()
+                            );
+                        }
+                    }
+                });
+            });
+        if !open {
+            self.image_dialog = None;
+        }
+    }
+
+    /// Draw the memory-mapped LED bank and the DIP switches under it
+    fn draw_board(&mut self, ui: &mut egui::Ui) {
+        let led_size = self.led_size;
+        let leds = self.machine.devices.leds(&self.machine.memory);
+        ui.horizontal(|ui| {
+            ui.add_sized([150.0, led_size], egui::Label::new("LEDs"))
+                .on_hover_text(format!("sw to 0x{LED_ADDRESS:08X} lights these"));
+            draw_bit_leds(ui, leds, 32, led_size, egui::Color32::from_rgb(60, 220, 90));
+        });
+        ui.horizontal(|ui| {
+            ui.add_sized([150.0, led_size], egui::Label::new("Switches"))
+                .on_hover_text(format!("lw from 0x{SWITCH_ADDRESS:08X} reads these"));
+            let mut switches = self.machine.devices.switches;
+            if draw_switches(ui, &mut switches, led_size) {
+                self.machine.set_switches(switches);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add_sized([150.0, led_size * 3.0], egui::Label::new("Digits"))
+                .on_hover_text(format!(
+                    "sb to 0x{SEGMENT_ADDRESS:08X} + n drives digit n, counting from the right"
+                ));
+            for digit in (0..SEGMENT_DIGITS).rev() {
+                let segments = self.machine.devices.segments(&self.machine.memory, digit);
+                let size = egui::vec2(led_size * 2.0, led_size * 3.0);
+                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+                paint_seven_segment(ui, rect, segments);
+                let address = SEGMENT_ADDRESS + digit;
+                response.on_hover_text(format!("Digit {digit} at 0x{address:08X}"));
+            }
+            ui.separator();
+            ui.checkbox(&mut self.machine.devices.hex_decode, "Hex decode")
+                .on_hover_text(
+                    "Decode the low nibble of each byte instead of lighting raw segments",
+                );
+        });
+    }
+
     /// Draw a memory row with 32 LEDs
     fn draw_memory_row(&mut self, ui: &mut egui::Ui, row_index: usize) {
-        if row_index >= self.memory_rows.len() {
+        if row_index >= self.num_rows {
             return;
         }
 
-        let row = &mut self.memory_rows[row_index];
-        
-        ui.horizontal(|ui| {
-            // Display memory address
-            ui.label(format!("0x{:08X}:", row.address));
-            ui.add_space(10.0);
-            
-            // Draw 32 LEDs (bits 31 to 0, left to right)
-            for bit_index in (0..32).rev() {
-                let is_on = row.get_bit(bit_index);
-                
-                // Make LED clickable to toggle bit
+        let address = self.row_address(row_index);
+        let mut row = MemoryRow::new(address, self.machine.memory.read_word(address));
+        let format = Format::of(row.data);
+        let is_pc = address == self.machine.cpu.pc;
+        let mut toggle_breakpoint = false;
+
+        // Highlight the row holding the next instruction to execute
+        let fill = if is_pc {
+            ui.visuals().selection.bg_fill.gamma_multiply(0.5)
+        } else {
+            egui::Color32::TRANSPARENT
+        };
+        egui::Frame::new().fill(fill).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                // Breakpoint gutter: click to toggle
                 let size = egui::Vec2::splat(self.led_size);
                 let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
-                
+                let response = response.on_hover_text("Toggle breakpoint");
                 if response.clicked() {
-                    row.set_bit(bit_index, !is_on);
+                    toggle_breakpoint = true;
                 }
-                
-                let color = if is_on {
-                    egui::Color32::from_rgb(255, 0, 0) // Red when on
-                } else {
-                    egui::Color32::from_rgb(64, 64, 64) // Dark gray when off
-                };
-                
-                ui.painter().circle_filled(rect.center(), self.led_size / 2.0, color);
-                
-                // Add a subtle border
-                ui.painter().circle_stroke(
-                    rect.center(),
-                    self.led_size / 2.0,
-                    egui::Stroke::new(1.0, egui::Color32::from_rgb(128, 128, 128)),
-                );
-                
-                // Add some spacing between LEDs
-                ui.add_space(2.0);
-            }
-            
-            // Display hex value
-            ui.add_space(10.0);
-            ui.label(format!("0x{:08X}", row.data));
+                if self.breakpoints.contains(&address) {
+                    ui.painter().circle_filled(
+                        rect.center(),
+                        self.led_size / 2.5,
+                        egui::Color32::from_rgb(200, 30, 30),
+                    );
+                } else if response.hovered() {
+                    ui.painter().circle_stroke(
+                        rect.center(),
+                        self.led_size / 2.5,
+                        egui::Stroke::new(1.0, egui::Color32::from_rgb(200, 30, 30)),
+                    );
+                }
+
+                // Cache gutter: which caches hold this word
+                if self.machine.icache.config.enabled || self.machine.dcache.config.enabled {
+                    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+                    let cached = [
+                        (
+                            "I-cache",
+                            &self.machine.icache,
+                            egui::Color32::from_rgb(80, 140, 230),
+                        ),
+                        (
+                            "D-cache",
+                            &self.machine.dcache,
+                            egui::Color32::from_rgb(60, 180, 90),
+                        ),
+                    ]
+                    .into_iter()
+                    .filter_map(|(name, cache, color)| {
+                        cache
+                            .lookup(address)
+                            .map(|(set, way)| (name, set, way, color))
+                    });
+                    let mut hover = Vec::new();
+                    for (i, (name, set, way, color)) in cached.enumerate() {
+                        // One half of the cell per cache
+                        let x = rect.left() + i as f32 * rect.width() / 2.0;
+                        let half = egui::Rect::from_min_size(
+                            egui::pos2(x, rect.top()),
+                            egui::vec2(rect.width() / 2.0, rect.height()),
+                        );
+                        ui.painter().rect_filled(half.shrink(1.0), 2.0, color);
+                        hover.push(format!("{name} set {set}, way {way}"));
+                    }
+                    if !hover.is_empty() {
+                        response.on_hover_text(format!("Cached in {}", hover.join("; ")));
+                    }
+                }
+
+                // Display memory address
+                ui.label(format!("0x{:08X}:", row.address));
+                ui.add_space(10.0);
+
+                // Draw 32 LEDs as four bytes in address order: big-endian shows
+                // bits 31 to 0, little-endian starts with byte 0 (bits 7 to 0)
+                let endianness = self.machine.memory.endianness();
+                let byte_shifts =
+                    std::array::from_fn(|b| endianness.byte_shift(address + b as u32));
+                let fields = self
+                    .field_colouring
+                    .then_some(LedFields::Instruction(format));
+                draw_leds(ui, &mut row.data, self.led_size, byte_shifts, fields, true);
+
+                // Display hex value
+                ui.add_space(10.0);
+                ui.label(format!("0x{:08X}", row.data));
+
+                // Label the row with the symbols defined at it
+                if let Some(names) = self.machine.symbols.get(&address) {
+                    ui.add_space(10.0);
+                    ui.strong(format!("{names}:"));
+                }
+
+                // Display the instruction this word encodes
+                ui.add_space(10.0);
+                match disasm::disassemble(row.data, row.address) {
+                    Some(text) => {
+                        ui.monospace(text);
+                    }
+                    None => {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            egui::RichText::new("??? invalid instruction").monospace(),
+                        );
+                    }
+                }
+
+                // Show which pseudo-instruction this word was expanded from
+                if let Some(source) = self.machine.pseudo_sources.get(&address) {
+                    ui.add_space(10.0);
+                    ui.label(
+                        egui::RichText::new(format!("← {source}"))
+                            .monospace()
+                            .weak(),
+                    );
+                }
+
+                // Tag the stages this instruction currently occupies
+                if self.pipelined {
+                    let stages: Vec<&str> = self.pipeline.stages_at(address).collect();
+                    if !stages.is_empty() {
+                        ui.add_space(10.0);
+                        ui.strong(stages.join(" "));
+                    }
+                }
+            });
         });
-    }
 
+        if toggle_breakpoint {
+            self.toggle_breakpoint(address);
+        }
+
+        if row.data != self.machine.memory.read_word(address) {
+            self.machine.memory.write_word(address, row.data);
+        }
+    }
 
     /// Called once before the first frame.
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
@@ -184,7 +1228,10 @@ impl TemplateApp {
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
         if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+            let mut app: Self = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+            // `Default` reset the CPU before the saved entry point was restored
+            app.reset_cpu();
+            return app;
         }
 
         Default::default()
@@ -206,21 +1253,115 @@ impl eframe::App for TemplateApp {
             // The top panel is often a good place for a menu bar:
 
             egui::menu::bar(ui, |ui| {
-                // NOTE: no File->Quit on web pages!
-                let is_web = cfg!(target_arch = "wasm32");
-                if !is_web {
-                    ui.menu_button("File", |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Import…").clicked() {
+                        self.image_dialog = Some(ImageDialog::Import);
+                        ui.close_menu();
+                    }
+                    if ui.button("Export…").clicked() {
+                        self.image_dialog = Some(ImageDialog::Export);
+                        ui.close_menu();
+                    }
+                    // NOTE: no File->Quit on web pages!
+                    let is_web = cfg!(target_arch = "wasm32");
+                    if !is_web {
+                        ui.separator();
                         if ui.button("Quit").clicked() {
                             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                         }
-                    });
-                    ui.add_space(16.0);
-                }
+                    }
+                });
+                ui.add_space(16.0);
 
                 egui::widgets::global_theme_preference_buttons(ui);
             });
         });
 
+        self.run_frame(ctx);
+        self.load_dropped_files(ctx);
+        self.draw_image_window(ctx);
+
+        egui::TopBottomPanel::bottom("console_panel")
+            .resizable(true)
+            .show(ctx, |ui| {
+                self.draw_console_panel(ui);
+            });
+
+        if self.show_terminal {
+            egui::TopBottomPanel::bottom("terminal_panel")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    self.draw_terminal_panel(ui);
+                });
+        }
+
+        if self.pipelined {
+            egui::TopBottomPanel::bottom("pipeline_panel")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    self.draw_pipeline_panel(ui);
+                });
+        }
+
+        egui::SidePanel::right("register_panel")
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.heading("Registers");
+                ui.separator();
+                self.draw_register_panel(ui);
+            });
+
+        if self.show_cache {
+            egui::SidePanel::right("cache_panel")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    self.draw_cache_panel(ui);
+                });
+        }
+
+        if self.show_bitmap {
+            egui::SidePanel::right("bitmap_panel")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    self.draw_bitmap_panel(ui);
+                });
+        }
+
+        egui::SidePanel::left("editor_panel")
+            .resizable(true)
+            .default_width(260.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("Assembly");
+                    if ui.button("Assemble").clicked() {
+                        self.assemble_source();
+                    }
+                });
+                if let Some(error) = &self.asm_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                }
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let error = self.asm_error.clone();
+                    let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                        let mut job = highlight_error(ui, text, error.as_ref());
+                        job.wrap.max_width = wrap_width;
+                        ui.fonts(|fonts| fonts.layout_job(job))
+                    };
+                    let response = ui.add(
+                        egui::TextEdit::multiline(&mut self.source)
+                            .code_editor()
+                            .desired_width(f32::INFINITY)
+                            .desired_rows(20)
+                            .layouter(&mut layouter),
+                    );
+                    if response.changed() {
+                        // The span no longer matches the text
+                        self.asm_error = None;
+                    }
+                });
+            });
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
             ui.heading("MIPS Emulator - LED Memory Display");
@@ -245,13 +1386,136 @@ impl eframe::App for TemplateApp {
                 ui.separator();
                 ui.label("LED Size:");
                 ui.add(egui::Slider::new(&mut self.led_size, 8.0..=20.0).text("px"));
+                ui.checkbox(&mut self.field_colouring, "Field colouring");
+                ui.checkbox(&mut self.show_cache, "Cache panel");
+                ui.checkbox(&mut self.show_board, "I/O board");
+                ui.checkbox(&mut self.show_terminal, "Terminal");
+                ui.checkbox(&mut self.show_bitmap, "Bitmap display");
+                ui.separator();
+                let mut endianness = self.machine.memory.endianness();
+                egui::ComboBox::from_id_salt("endianness")
+                    .selected_text(match endianness {
+                        Endianness::Big => "Big endian",
+                        Endianness::Little => "Little endian",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut endianness, Endianness::Big, "Big endian");
+                        ui.selectable_value(
+                            &mut endianness,
+                            Endianness::Little,
+                            "Little endian",
+                        );
+                    });
+                self.machine.memory.set_endianness(endianness);
             });
 
+            if self.field_colouring {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("Fields:");
+                    for field in Field::ALL {
+                        ui.colored_label(field_color(field), field.name());
+                    }
+                    ui.separator();
+                    ui.label("R: opcode rs rt rd shamt funct  I: opcode rs rt immediate  J: opcode target");
+                });
+            }
+
+            // Execution controls
+            ui.horizontal(|ui| {
+                let step_hint = if self.pipelined {
+                    "Advance the pipeline one clock cycle"
+                } else {
+                    "Execute the instruction at PC"
+                };
+                let step = ui.add_enabled(!self.running, egui::Button::new("Step"));
+                if step.on_hover_text(step_hint).clicked() {
+                    self.step_and_report(ui.input(|i| i.time));
+                }
+                if self.running {
+                    if ui.button("Pause").clicked() {
+                        self.running = false;
+                    }
+                } else if ui.button("Run").clicked() {
+                    self.start_running();
+                }
+                if ui.button("Reset").clicked() {
+                    self.reset_cpu();
+                }
+                ui.separator();
+                ui.label("Clock:");
+                ui.add(
+                    egui::Slider::new(&mut self.clock_hz, 1.0..=1000.0)
+                        .logarithmic(true)
+                        .text("Hz"),
+                );
+                ui.separator();
+                let mut pipelined = self.pipelined;
+                if ui.checkbox(&mut pipelined, "Pipelined").changed() {
+                    self.set_pipelined(pipelined);
+                }
+                ui.separator();
+                ui.monospace(format!("PC: 0x{:08X}", self.machine.cpu.pc));
+            });
+            if !self.status.is_empty() {
+                ui.colored_label(ui.visuals().warn_fg_color, &self.status);
+            }
+
             ui.separator();
 
+            // Choose which window of the address space the LED grid shows
+            ui.horizontal(|ui| {
+                let page = (self.num_rows.max(1) as u32).wrapping_mul(4);
+                if ui.button("⏴").on_hover_text("Previous page").clicked() {
+                    self.set_view_base(self.view_base.wrapping_sub(page));
+                }
+                if ui.button("⏵").on_hover_text("Next page").clicked() {
+                    self.set_view_base(self.view_base.wrapping_add(page));
+                }
+                ui.label("Go to:");
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.view_input)
+                        .hint_text("0x00000000")
+                        .desired_width(90.0),
+                );
+                let submitted =
+                    response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if submitted || ui.button("Go").clicked() {
+                    match parse_address(&self.view_input) {
+                        Some(address) => self.set_view_base(address),
+                        None => self.status = format!("Invalid address '{}'", self.view_input),
+                    }
+                }
+                if ui.button("PC").on_hover_text("Show the row at PC").clicked() {
+                    self.set_view_base(self.machine.cpu.pc);
+                }
+                if ui.button(".text").on_hover_text("Show the text segment").clicked() {
+                    self.set_view_base(TEXT_BASE);
+                }
+                if ui.button(".data").on_hover_text("Show the data segment").clicked() {
+                    self.set_view_base(DATA_BASE);
+                }
+                if ui.button(".ktext").on_hover_text("Show the exception handler").clicked() {
+                    self.set_view_base(KTEXT_BASE);
+                }
+                ui.separator();
+                ui.label(format!(
+                    "0x{:08X} – 0x{:08X}, {} page(s) allocated",
+                    self.view_base,
+                    self.row_address(self.num_rows.max(1) - 1).wrapping_add(3),
+                    self.machine.memory.mapped_pages()
+                ));
+            });
+
+            ui.separator();
+
+            if self.show_board {
+                self.draw_board(ui);
+                ui.separator();
+            }
+
             // Display memory rows with LEDs
             egui::ScrollArea::vertical().show(ui, |ui| {
-                for i in 0..self.memory_rows.len() {
+                for i in 0..self.num_rows {
                     self.draw_memory_row(ui, i);
                     ui.add_space(5.0);
                 }
@@ -261,10 +1525,7 @@ impl eframe::App for TemplateApp {
 
             // Instructions
             ui.label("Instructions:");
-            ui.label("• Click on any LED to toggle its state (red = 1, gray = 0)");
-            ui.label("• Each row represents a 32-bit memory word");
-            ui.label("• Memory addresses start at 0x00000000 and increment by 4");
-            ui.label("• Use Add/Remove Row buttons to manage memory size");
+            ui.label(INSTRUCTIONS);
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                 egui::warn_if_debug_build(ui);
@@ -273,6 +1534,446 @@ impl eframe::App for TemplateApp {
     }
 }
 
+/// Which IEEE-754 fields an FPU register holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FloatWord {
+    /// A single: sign, 8-bit exponent and 23-bit fraction
+    Single,
+    /// Odd register of a double: sign, 11-bit exponent and the top 20 fraction bits
+    DoubleHigh,
+    /// Even register of a double: the low 32 fraction bits
+    DoubleLow,
+}
+
+/// What the LEDs of a row are tinted by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LedFields {
+    Instruction(Format),
+    Float(FloatWord),
+}
+
+impl LedFields {
+    /// Name, bit positions and colour of the field bit `bit` belongs to
+    fn field_at(self, bit: u32) -> (&'static str, std::ops::RangeInclusive<u32>, egui::Color32) {
+        let sign = egui::Color32::from_rgb(230, 70, 70);
+        let exponent = egui::Color32::from_rgb(90, 120, 240);
+        let fraction = egui::Color32::from_rgb(80, 200, 90);
+        match self {
+            Self::Instruction(format) => {
+                let field = format.field_at(bit);
+                (field.name(), field.bits(), field_color(field))
+            }
+            Self::Float(FloatWord::DoubleLow) => ("fraction", 0..=31, fraction),
+            Self::Float(word) => {
+                let fraction_bits = if word == FloatWord::Single { 23 } else { 20 };
+                match bit {
+                    31 => ("sign", 31..=31, sign),
+                    b if b >= fraction_bits => ("exponent", fraction_bits..=30, exponent),
+                    _ => ("fraction", 0..=fraction_bits - 1, fraction),
+                }
+            }
+        }
+    }
+}
+
+/// Draw 32 clickable LEDs for `value`, grouped into bytes
+///
+/// `byte_shifts` lists the bit offset of each byte from left to right. With
+/// `fields` set, each LED is tinted by the instruction or IEEE-754 field it
+/// belongs to.
+fn draw_leds(
+    ui: &mut egui::Ui,
+    value: &mut u32,
+    led_size: f32,
+    byte_shifts: [u32; 4],
+    fields: Option<LedFields>,
+    editable: bool,
+) {
+    for (byte, shift) in byte_shifts.into_iter().enumerate() {
+        if byte > 0 {
+            ui.add_space(led_size / 2.0);
+        }
+        for bit_index in (shift..shift + 8).rev() {
+            let is_on = (*value >> bit_index) & 1 == 1;
+
+            // Make LED clickable to toggle bit
+            let size = egui::Vec2::splat(led_size);
+            let sense = if editable {
+                egui::Sense::click()
+            } else {
+                egui::Sense::hover()
+            };
+            let (rect, response) = ui.allocate_exact_size(size, sense);
+            let response = match fields {
+                Some(fields) => {
+                    let (name, bits, _) = fields.field_at(bit_index);
+                    response.on_hover_text(format!(
+                        "Bit {bit_index}: {name} (bits {}..{})",
+                        bits.end(),
+                        bits.start()
+                    ))
+                }
+                None => response,
+            };
+
+            if response.clicked() {
+                *value ^= 1 << bit_index;
+            }
+
+            let color = if let Some(fields) = fields {
+                let (_, _, tint) = fields.field_at(bit_index);
+                if is_on {
+                    tint
+                } else {
+                    // Dim the field colour so unlit LEDs still show their field
+                    egui::Color32::from_rgb(tint.r() / 4, tint.g() / 4, tint.b() / 4)
+                }
+            } else if is_on {
+                egui::Color32::from_rgb(255, 0, 0) // Red when on
+            } else {
+                egui::Color32::from_rgb(64, 64, 64) // Dark gray when off
+            };
+
+            paint_led(ui, rect, led_size, color);
+
+            // Add some spacing between LEDs
+            ui.add_space(2.0);
+        }
+    }
+}
+
+/// Draw one register as a name, 32 LEDs and its hex value
+///
+/// `flash` fades the highlight on a register the last instruction wrote.
+fn draw_register_row(
+    ui: &mut egui::Ui,
+    name: &str,
+    value: &mut u32,
+    led_size: f32,
+    editable: bool,
+    written: bool,
+    flash: f32,
+) {
+    ui.horizontal(|ui| {
+        ui.label(register_label(name, written, flash));
+        ui.add_space(6.0);
+        draw_leds(ui, value, led_size, [24, 16, 8, 0], None, editable);
+        ui.add_space(6.0);
+        ui.monospace(format!("0x{value:08X}"));
+    });
+}
+
+/// A register name, highlighted while `flash` fades if it was just written
+fn register_label(name: &str, written: bool, flash: f32) -> egui::RichText {
+    let label = egui::RichText::new(format!("{name:>5}")).monospace();
+    if written && flash > 0.0 {
+        let alpha = (flash * 200.0) as u8;
+        label.background_color(egui::Color32::from_rgba_unmultiplied(255, 200, 0, alpha))
+    } else {
+        label
+    }
+}
+
+/// Draw FPU register `index` with LEDs tinted by IEEE-754 field, its hex
+/// value and the float it decodes to
+///
+/// With `doubles`, the even register of each pair shows the double the pair holds.
+fn draw_fp_register_row(
+    ui: &mut egui::Ui,
+    cp1: &mut Cp1,
+    index: usize,
+    doubles: bool,
+    led_size: f32,
+    written: bool,
+    flash: f32,
+) {
+    let (word, decoded) = match (doubles, index % 2) {
+        (false, _) => (FloatWord::Single, format!("{:?}", cp1.single(index))),
+        (true, 0) => (FloatWord::DoubleLow, format!("{:?}", cp1.double(index))),
+        (true, _) => (FloatWord::DoubleHigh, String::new()),
+    };
+    ui.horizontal(|ui| {
+        ui.label(register_label(FP_REGISTER_NAMES[index], written, flash));
+        ui.add_space(6.0);
+        let value = &mut cp1.regs[index];
+        let fields = Some(LedFields::Float(word));
+        draw_leds(ui, value, led_size, [24, 16, 8, 0], fields, true);
+        ui.add_space(6.0);
+        ui.monospace(format!("0x{value:08X}"));
+        ui.monospace(decoded);
+    });
+}
+
+/// Decode Cause and Status below the CP0 registers
+fn draw_cp0_summary(ui: &mut egui::Ui, cp0: &Cp0) {
+    let code = cp0.exception_code();
+    ui.monospace(format!(
+        "Cause: {code} {}, pending IP 0x{:02X}",
+        cp0::exc_code_name(code),
+        (cp0.cause & cp0::INTERRUPT_MASK) >> 8
+    ));
+    let flag = |bit: u32| if cp0.status & bit != 0 { "on" } else { "off" };
+    ui.monospace(format!(
+        "Status: IE {}, EXL {}, mask 0x{:02X}",
+        flag(cp0::STATUS_IE),
+        flag(cp0::STATUS_EXL),
+        (cp0.status & cp0::INTERRUPT_MASK) >> 8
+    ));
+}
+
+/// Draw each cache line as its set and way, V and D bits, tag LEDs and block address
+fn draw_cache_lines(ui: &mut egui::Ui, cache: &Cache, led_size: f32) {
+    let tag_bits = cache.config.tag_bits();
+    for (set, lines) in cache.sets().iter().enumerate() {
+        for (way, line) in lines.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{set:>3}.{way}"));
+                draw_bit_leds(
+                    ui,
+                    line.valid as u32,
+                    1,
+                    led_size,
+                    egui::Color32::from_rgb(60, 180, 90),
+                )
+                .on_hover_text("Valid");
+                draw_bit_leds(
+                    ui,
+                    line.dirty as u32,
+                    1,
+                    led_size,
+                    egui::Color32::from_rgb(230, 160, 30),
+                )
+                .on_hover_text("Dirty");
+                ui.add_space(led_size / 2.0);
+                draw_bit_leds(
+                    ui,
+                    line.tag,
+                    tag_bits,
+                    led_size,
+                    egui::Color32::from_rgb(255, 0, 0),
+                )
+                .on_hover_text(format!("Tag 0x{:X}", line.tag));
+                if line.valid {
+                    ui.monospace(format!("0x{:08X}", cache.block_address(set, line)));
+                }
+            });
+        }
+    }
+}
+
+/// Draw the low `bits` bits of `value` as read-only LEDs, most significant first
+fn draw_bit_leds(
+    ui: &mut egui::Ui,
+    value: u32,
+    bits: u32,
+    led_size: f32,
+    on_color: egui::Color32,
+) -> egui::Response {
+    ui.horizontal(|ui| {
+        for bit_index in (0..bits).rev() {
+            let (rect, _) =
+                ui.allocate_exact_size(egui::Vec2::splat(led_size), egui::Sense::hover());
+            let color = if (value >> bit_index) & 1 == 1 {
+                on_color
+            } else {
+                egui::Color32::from_rgb(64, 64, 64)
+            };
+            paint_led(ui, rect, led_size, color);
+            ui.add_space(2.0);
+        }
+    })
+    .response
+}
+
+/// Draw 32 clickable DIP switches, bit 31 leftmost; returns whether one was flipped
+fn draw_switches(ui: &mut egui::Ui, value: &mut u32, led_size: f32) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        for bit_index in (0..32).rev() {
+            let size = egui::vec2(led_size, led_size * 1.6);
+            let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+            if response.clicked() {
+                *value ^= 1 << bit_index;
+                changed = true;
+            }
+            let on = (*value >> bit_index) & 1 == 1;
+            let painter = ui.painter();
+            painter.rect_filled(rect, 2.0, egui::Color32::from_rgb(40, 60, 140));
+            // The lever sits in the upper half when the switch is on
+            let lever = if on {
+                rect.shrink(2.0).split_top_bottom_at_fraction(0.5).0
+            } else {
+                rect.shrink(2.0).split_top_bottom_at_fraction(0.5).1
+            };
+            painter.rect_filled(lever, 1.0, egui::Color32::from_rgb(230, 230, 230));
+            response.on_hover_text(format!(
+                "Switch {bit_index}: {}",
+                if on { "on" } else { "off" }
+            ));
+            ui.add_space(2.0);
+        }
+    });
+    changed
+}
+
+/// Combo box choosing one of the bitmap display's pixel sizes
+fn size_choice(ui: &mut egui::Ui, id: &str, value: &mut u32, choices: &[u32]) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(format!("{value} px"))
+        .show_ui(ui, |ui| {
+            for &choice in choices {
+                ui.selectable_value(value, choice, format!("{choice} px"));
+            }
+        });
+}
+
+/// A bitmap display base address and, for the usual ones, what lives there
+fn describe_base(base: u32) -> String {
+    match bitmap::BASE_ADDRESSES
+        .iter()
+        .find(|(address, _)| *address == base)
+    {
+        Some((_, name)) => format!("0x{base:08X} ({name})"),
+        None => format!("0x{base:08X}"),
+    }
+}
+
+/// Draw a seven-segment digit: bits 0–6 light segments a–g and bit 7 the decimal point
+fn paint_seven_segment(ui: &egui::Ui, rect: egui::Rect, segments: u8) {
+    let width = rect.width();
+    let thickness = width * 0.12;
+    let (left, right) = (rect.left() + width * 0.2, rect.right() - width * 0.3);
+    let (top, bottom) = (rect.top() + width * 0.2, rect.bottom() - width * 0.2);
+    let middle = rect.center().y;
+    let point = egui::pos2;
+    // Segments a to g, clockwise from the top then the middle bar
+    let lines = [
+        [point(left, top), point(right, top)],
+        [point(right, top), point(right, middle)],
+        [point(right, middle), point(right, bottom)],
+        [point(left, bottom), point(right, bottom)],
+        [point(left, middle), point(left, bottom)],
+        [point(left, top), point(left, middle)],
+        [point(left, middle), point(right, middle)],
+    ];
+    let color = |lit: bool| {
+        if lit {
+            egui::Color32::from_rgb(255, 50, 50)
+        } else {
+            egui::Color32::from_rgb(64, 64, 64)
+        }
+    };
+    let painter = ui.painter();
+    for (bit, line) in lines.into_iter().enumerate() {
+        let lit = (segments >> bit) & 1 == 1;
+        painter.line_segment(line, egui::Stroke::new(thickness, color(lit)));
+    }
+    let dp = point(rect.right() - width * 0.12, bottom);
+    painter.circle_filled(dp, thickness * 0.7, color(segments & SEGMENT_DP != 0));
+}
+
+/// Draw one round LED with a subtle border
+fn paint_led(ui: &egui::Ui, rect: egui::Rect, led_size: f32, color: egui::Color32) {
+    ui.painter()
+        .circle_filled(rect.center(), led_size / 2.0, color);
+    ui.painter().circle_stroke(
+        rect.center(),
+        led_size / 2.0,
+        egui::Stroke::new(1.0, egui::Color32::from_rgb(128, 128, 128)),
+    );
+}
+
+/// Draw one cell of the pipeline diagram
+fn draw_slot(ui: &mut egui::Ui, slot: &Slot) {
+    match slot {
+        Slot::Empty => {
+            ui.label("");
+        }
+        Slot::Bubble => {
+            ui.colored_label(ui.visuals().warn_fg_color, "bubble")
+                .on_hover_text("Stall: a nop inserted while a hazard holds IF and ID");
+        }
+        Slot::Flushed(pc) => {
+            ui.colored_label(ui.visuals().error_fg_color, "flushed")
+                .on_hover_text(format!(
+                    "Instruction at 0x{pc:08X} squashed after a branch or jump"
+                ));
+        }
+        Slot::Instruction(instruction) => {
+            let text = disasm::disassemble(instruction.word, instruction.pc)
+                .unwrap_or_else(|| "???".to_owned());
+            ui.monospace(text)
+                .on_hover_text(format!("0x{:08X}", instruction.pc));
+        }
+    }
+}
+
+/// Lay out editor text in the code font, underlining the span `error` points at
+fn highlight_error(ui: &egui::Ui, text: &str, error: Option<&AsmError>) -> egui::text::LayoutJob {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let plain = egui::TextFormat::simple(font_id, ui.visuals().text_color());
+    let mut job = egui::text::LayoutJob::default();
+
+    let Some(span) = error.and_then(|error| error_span(text, error)) else {
+        job.append(text, 0.0, plain);
+        return job;
+    };
+    let marked = egui::TextFormat {
+        underline: egui::Stroke::new(2.0, ui.visuals().error_fg_color),
+        background: ui.visuals().error_fg_color.gamma_multiply(0.15),
+        ..plain.clone()
+    };
+    job.append(&text[..span.start], 0.0, plain.clone());
+    job.append(&text[span.clone()], 0.0, marked);
+    job.append(&text[span.end..], 0.0, plain);
+    job
+}
+
+/// Byte range of `text` covered by the error's line, column and length
+fn error_span(text: &str, error: &AsmError) -> Option<std::ops::Range<usize>> {
+    let line_start = if error.line == 1 {
+        0
+    } else {
+        text.match_indices('\n').nth(error.line - 2)?.0 + 1
+    };
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let mut boundaries = line
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(line.len()))
+        .skip(error.column - 1);
+    let start = boundaries.next()?;
+    let end = boundaries
+        .nth(error.length.saturating_sub(1))
+        .unwrap_or(line.len());
+    Some(line_start + start..line_start + end)
+}
+
+/// Parse a hexadecimal address, with or without a `0x` prefix
+fn parse_address(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// LED colour used for an instruction field in field colouring mode
+fn field_color(field: Field) -> egui::Color32 {
+    match field {
+        Field::Opcode => egui::Color32::from_rgb(230, 70, 70),
+        Field::Rs => egui::Color32::from_rgb(240, 160, 40),
+        Field::Rt => egui::Color32::from_rgb(230, 220, 60),
+        Field::Rd => egui::Color32::from_rgb(80, 200, 90),
+        Field::Shamt => egui::Color32::from_rgb(60, 190, 220),
+        Field::Funct => egui::Color32::from_rgb(90, 120, 240),
+        Field::Imm => egui::Color32::from_rgb(180, 100, 230),
+        Field::Target => egui::Color32::from_rgb(230, 110, 190),
+    }
+}
+
 // fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
 //     ui.horizontal(|ui| {
 //         ui.spacing_mut().item_spacing.x = 0.0;
@@ -286,3 +1987,105 @@ impl eframe::App for TemplateApp {
 //         ui.label(".");
 //     });
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app with `source` assembled and a clock fast enough that one
+    /// frame covers every step the tests need
+    fn app(source: &str) -> TemplateApp {
+        let mut app = TemplateApp {
+            source: source.to_owned(),
+            clock_hz: 60_000.0,
+            ..TemplateApp::default()
+        };
+        app.assemble_source();
+        assert!(app.asm_error.is_none(), "{}", app.status);
+        app
+    }
+
+    /// Print 2, 1 and 0, then exit
+    const COUNTDOWN: &str = "
+        .text
+        main:   li   $t0, 3
+        loop:   addi $t0, $t0, -1
+                move $a0, $t0
+                li   $v0, 1
+                syscall
+                bgt  $t0, $zero, loop
+                li   $v0, 10
+                syscall
+        ";
+
+    #[test]
+    fn run_steps_until_the_program_exits() {
+        let mut app = app(COUNTDOWN);
+        let ctx = egui::Context::default();
+        app.run_frame(&ctx);
+        assert!(!app.running);
+        app.start_running();
+        app.run_frame(&ctx);
+        assert!(!app.running);
+        assert_eq!(app.machine.exit_code(), Some(0));
+        assert_eq!(app.status, "Program exited with code 0");
+        assert_eq!(app.console.output, "210");
+    }
+
+    #[test]
+    fn clock_rate_limits_steps_per_frame() {
+        let mut app = app(COUNTDOWN);
+        // One step per frame at the default 60 Hz frame rate
+        app.clock_hz = 60.0;
+        let ctx = egui::Context::default();
+        app.start_running();
+        let entry = app.machine.cpu.pc;
+        app.run_frame(&ctx);
+        assert_eq!(app.machine.cpu.pc, entry + 4);
+        app.run_frame(&ctx);
+        assert_eq!(app.machine.cpu.pc, entry + 8);
+        assert!(app.running);
+        // Paused, frames leave the machine alone
+        app.running = false;
+        app.run_frame(&ctx);
+        assert_eq!(app.machine.cpu.pc, entry + 8);
+    }
+
+    #[test]
+    fn run_stops_at_a_breakpoint_and_resumes_past_it() {
+        for pipelined in [false, true] {
+            breakpoint_round_trip(pipelined);
+        }
+    }
+
+    /// Stop at, resume past and then clear a breakpoint on the first syscall
+    fn breakpoint_round_trip(pipelined: bool) {
+        let mut app = app(COUNTDOWN);
+        app.set_pipelined(pipelined);
+        let ctx = egui::Context::default();
+        let syscall = app.machine.cpu.pc + 16;
+        app.toggle_breakpoint(syscall);
+        app.start_running();
+        app.run_frame(&ctx);
+        assert!(!app.running);
+        assert_eq!(app.machine.cpu.pc, syscall);
+        assert_eq!(app.status, format!("Breakpoint at 0x{syscall:08X}"));
+        assert_eq!(app.console.output, "");
+
+        // Run again executes the instruction under the breakpoint, then
+        // stops when the loop comes back to it
+        app.start_running();
+        app.run_frame(&ctx);
+        assert!(!app.running);
+        assert_eq!(app.machine.cpu.pc, syscall);
+        assert_eq!(app.console.output, "2");
+
+        // Without the breakpoint the run carries on to the end
+        app.toggle_breakpoint(syscall);
+        assert!(app.breakpoints.is_empty());
+        app.start_running();
+        app.run_frame(&ctx);
+        assert_eq!(app.machine.exit_code(), Some(0));
+        assert_eq!(app.console.output, "210");
+    }
+}
@@ -0,0 +1,1402 @@
+//! Two-pass MIPS assembler with MARS-style directives and segments
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cp1;
+use crate::isa::{self, InstrSpec, Operands};
+use crate::memory::{Endianness, Memory};
+
+/// Default base address of the `.text` segment, as in MARS
+pub const TEXT_BASE: u32 = 0x0040_0000;
+/// Default base address of the `.data` segment, as in MARS
+pub const DATA_BASE: u32 = 0x1001_0000;
+/// Default base address of `.ktext`: the exception handler, so a bare
+/// `.ktext` is enough to install one
+pub const KTEXT_BASE: u32 = crate::cp0::EXCEPTION_VECTOR;
+/// Default base address of the `.kdata` segment, as in MARS
+pub const KDATA_BASE: u32 = 0x9000_0000;
+
+/// Where the regions of the memory map end: user text, user data, kernel
+/// text, kernel data and the memory-mapped devices
+const REGION_ENDS: [u64; 5] = [
+    0x1000_0000,
+    0x8000_0000,
+    KDATA_BASE as u64,
+    crate::mmio::MMIO_BASE as u64,
+    1 << 32,
+];
+
+/// An assembly failure and the span of source text it points at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based source line
+    pub line: usize,
+    /// 1-based column, in characters, of the first character of the span
+    pub column: usize,
+    /// Length of the span in characters
+    pub length: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Which part of the address space a statement is assembled into
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SegmentKind {
+    Text,
+    Data,
+    /// Kernel code; starts at the exception handler
+    KText,
+    KData,
+}
+
+impl SegmentKind {
+    pub const ALL: [Self; 4] = [Self::Text, Self::Data, Self::KText, Self::KData];
+
+    /// Address the segment starts at unless its directive gives one
+    pub fn base(self) -> u32 {
+        match self {
+            Self::Text => TEXT_BASE,
+            Self::Data => DATA_BASE,
+            Self::KText => KTEXT_BASE,
+            Self::KData => KDATA_BASE,
+        }
+    }
+
+    /// Whether the segment holds instructions
+    pub fn is_code(self) -> bool {
+        matches!(self, Self::Text | Self::KText)
+    }
+
+    fn directive(self) -> &'static str {
+        match self {
+            Self::Text => ".text",
+            Self::Data => ".data",
+            Self::KText => ".ktext",
+            Self::KData => ".kdata",
+        }
+    }
+}
+
+/// A contiguous run of assembled bytes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    pub address: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Assembler output, ready to be loaded into memory
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub segments: Vec<Segment>,
+    /// Address of `main` if defined, otherwise the start of `.text`
+    pub entry: u32,
+    /// Byte order the data and instructions were laid out in
+    pub endianness: Endianness,
+    pub labels: HashMap<String, u32>,
+    /// Source text of the pseudo-instruction each expanded word came from
+    pub pseudo_sources: BTreeMap<u32, String>,
+    /// The code was compiled for branch delay slots
+    pub delay_slots: bool,
+}
+
+impl Program {
+    /// Copy every segment into `memory`, switching it to the program's byte order
+    pub fn load_into(&self, memory: &mut Memory) {
+        memory.set_endianness(self.endianness);
+        for segment in &self.segments {
+            for (i, &byte) in segment.bytes.iter().enumerate() {
+                memory.store_byte(segment.address.wrapping_add(i as u32), byte);
+            }
+        }
+    }
+
+    /// Number of instruction words in the text segments
+    pub fn instruction_count(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|s| s.kind.is_code())
+            .map(|s| s.bytes.len() / 4)
+            .sum()
+    }
+}
+
+/// What a statement assembles into
+enum StatementKind {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    /// `.word`, `.half` or `.byte` values, resolved in pass 2 so labels work
+    Values { width: u32, values: Vec<String> },
+    /// `.ascii` and `.asciiz` contents, known in pass 1
+    Bytes(Vec<u8>),
+    /// `.space`: bytes left as the zeros memory holds when the program is loaded
+    Space,
+}
+
+/// A line of source, kept so errors can point at the text they are about
+#[derive(Clone)]
+struct SourceLine {
+    line: usize,
+    /// The line without its comment
+    text: String,
+    /// Byte offset of the mnemonic or directive in `text`
+    start: usize,
+}
+
+impl SourceLine {
+    /// The mnemonic or directive as written
+    fn mnemonic(&self) -> &str {
+        self.text[self.start..]
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+    }
+
+    /// An error underlining the mnemonic or directive
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        self.error_span(self.start, self.mnemonic(), message)
+    }
+
+    /// An error underlining `token`, or the mnemonic if `token` isn't written
+    /// on the line (for example because `.eqv` substituted it)
+    fn error_at(&self, token: &str, message: impl Into<String>) -> AsmError {
+        match find_token(&self.text, self.start, token) {
+            Some(offset) => self.error_span(offset, token, message),
+            None => self.error(message),
+        }
+    }
+
+    fn error_span(&self, offset: usize, token: &str, message: impl Into<String>) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.text[..offset].chars().count() + 1,
+            length: token.chars().count().max(1),
+            message: message.into(),
+        }
+    }
+}
+
+/// Byte offset of the first occurrence of `token` at or after `from` that
+/// isn't part of a longer word, so `5` doesn't match inside `$t5`
+fn find_token(text: &str, from: usize, token: &str) -> Option<usize> {
+    if token.is_empty() {
+        return None;
+    }
+    let is_word = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$');
+    let mut search = from;
+    while let Some(found) = text.get(search..)?.find(token) {
+        let offset = search + found;
+        let end = offset + token.len();
+        let before = text[..offset].chars().next_back();
+        let after = text[end..].chars().next();
+        if !before.is_some_and(is_word) && !after.is_some_and(is_word) {
+            return Some(offset);
+        }
+        search = offset + token.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/// One instruction or data directive with its final address
+struct Statement {
+    source: SourceLine,
+    segment: SegmentKind,
+    address: u32,
+    kind: StatementKind,
+}
+
+impl Statement {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        self.source.error(message)
+    }
+
+    fn error_at(&self, token: &str, message: impl Into<String>) -> AsmError {
+        self.source.error_at(token, message)
+    }
+}
+
+/// Pass 1 state: where each segment is up to and what has been defined
+struct Layout {
+    labels: HashMap<String, u32>,
+    eqvs: HashMap<String, String>,
+    /// Labels seen since the last statement, bound once its (aligned) address is known
+    pending_labels: Vec<(SourceLine, String)>,
+    segment: SegmentKind,
+    next_address: HashMap<SegmentKind, u32>,
+    statements: Vec<Statement>,
+}
+
+impl Layout {
+    fn address(&self) -> u32 {
+        self.next_address[&self.segment]
+    }
+
+    fn align(&mut self, alignment: u32) {
+        let address = self.address();
+        let aligned = address.wrapping_add(alignment - 1) & !(alignment - 1);
+        self.next_address.insert(self.segment, aligned);
+    }
+
+    fn bind_labels(&mut self) -> Result<(), AsmError> {
+        let address = self.address();
+        for (source, label) in std::mem::take(&mut self.pending_labels) {
+            if self.labels.insert(label.clone(), address).is_some() {
+                return Err(source.error_at(&label, format!("label '{label}' is already defined")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Place a statement of `size` bytes at the current address, which must
+    /// fit in the region of the memory map it starts in
+    fn push(
+        &mut self,
+        source: &SourceLine,
+        size: u32,
+        kind: StatementKind,
+    ) -> Result<(), AsmError> {
+        self.bind_labels()?;
+        let address = self.address();
+        let region_end = REGION_ENDS.into_iter().find(|&end| end > address as u64);
+        if region_end.is_some_and(|end| address as u64 + size as u64 > end) {
+            return Err(source.error(format!(
+                "'{}' runs past the end of the {} segment",
+                source.mnemonic(),
+                self.segment.directive()
+            )));
+        }
+        self.statements.push(Statement {
+            source: source.clone(),
+            segment: self.segment,
+            address,
+            kind,
+        });
+        self.next_address
+            .insert(self.segment, address.wrapping_add(size));
+        Ok(())
+    }
+}
+
+/// Assemble `source`, laying out multi-byte values in `endianness` order
+pub fn assemble(source: &str, endianness: Endianness) -> Result<Program, AsmError> {
+    let mut layout = Layout {
+        labels: HashMap::new(),
+        eqvs: HashMap::new(),
+        pending_labels: Vec::new(),
+        segment: SegmentKind::Text,
+        next_address: SegmentKind::ALL
+            .into_iter()
+            .map(|kind| (kind, kind.base()))
+            .collect(),
+        statements: Vec::new(),
+    };
+
+    // Pass 1: collect labels and assign an address to every statement
+    for (index, raw) in source.lines().enumerate() {
+        let mut source = SourceLine {
+            line: index + 1,
+            text: strip_comment(raw).to_owned(),
+            start: 0,
+        };
+        let substituted = substitute_eqvs(&source.text, &layout.eqvs);
+        let mut text = substituted.trim();
+        let mut labels = 0;
+
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if label.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+                // The colon belongs to an operand such as a string
+                break;
+            }
+            if !is_identifier(label) {
+                return Err(source.error_at(label, format!("invalid label name '{label}'")));
+            }
+            layout
+                .pending_labels
+                .push((source.clone(), label.to_owned()));
+            text = rest.trim();
+            labels += 1;
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+        // `.eqv` may have changed the line's length, so find the mnemonic by
+        // skipping the same labels in the text as written
+        let mut written = source.text.trim_start();
+        for _ in 0..labels {
+            written = written
+                .split_once(':')
+                .map_or("", |(_, rest)| rest.trim_start());
+        }
+        source.start = source.text.len() - written.len();
+        let error = |message: String| source.error(message);
+        let error_at = |token: &str, message: String| source.error_at(token, message);
+
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mnemonic = mnemonic.to_lowercase();
+        let operands = split_operands(rest);
+
+        if !mnemonic.starts_with('.') {
+            if !layout.segment.is_code() {
+                return Err(error(format!(
+                    "instruction '{mnemonic}' outside the .text and .ktext segments"
+                )));
+            }
+            // Labels don't change how many words a pseudo-instruction needs
+            let words =
+                expand(&source, &mnemonic, &operands, &|_| Ok(0))?.map_or(1, |basics| basics.len());
+            layout.align(4);
+            layout.push(
+                &source,
+                4 * words as u32,
+                StatementKind::Instruction { mnemonic, operands },
+            )?;
+            continue;
+        }
+
+        let number = |text: &str| {
+            parse_int(text).filter(|&n| n >= 0).ok_or_else(|| {
+                error_at(
+                    text,
+                    format!("expected a non-negative number, found '{text}'"),
+                )
+            })
+        };
+        match mnemonic.as_str() {
+            ".text" | ".data" | ".ktext" | ".kdata" => {
+                layout.bind_labels()?;
+                layout.segment = SegmentKind::ALL
+                    .into_iter()
+                    .find(|kind| kind.directive() == mnemonic)
+                    .unwrap_or(SegmentKind::Text);
+                if let [address] = operands.as_slice() {
+                    layout
+                        .next_address
+                        .insert(layout.segment, number(address)? as u32);
+                } else if !operands.is_empty() {
+                    return Err(error(format!("'{mnemonic}' takes at most one address")));
+                }
+            }
+            ".word" | ".half" | ".byte" => {
+                if operands.is_empty() {
+                    return Err(error(format!("'{mnemonic}' needs at least one value")));
+                }
+                let width = match mnemonic.as_str() {
+                    ".word" => 4,
+                    ".half" => 2,
+                    _ => 1,
+                };
+                layout.align(width);
+                let size = width * operands.len() as u32;
+                layout.push(
+                    &source,
+                    size,
+                    StatementKind::Values {
+                        width,
+                        values: operands,
+                    },
+                )?;
+            }
+            ".float" | ".double" => {
+                if operands.is_empty() {
+                    return Err(error(format!("'{mnemonic}' needs at least one value")));
+                }
+                let width = if mnemonic == ".float" { 4 } else { 8 };
+                let mut bytes = Vec::new();
+                for operand in &operands {
+                    let value = operand.parse::<f64>().map_err(|_| {
+                        error_at(operand, format!("invalid floating-point value '{operand}'"))
+                    })?;
+                    let mut value_bytes = if width == 4 {
+                        (value as f32).to_be_bytes().to_vec()
+                    } else {
+                        value.to_be_bytes().to_vec()
+                    };
+                    if endianness == Endianness::Little {
+                        value_bytes.reverse();
+                    }
+                    bytes.extend(value_bytes);
+                }
+                layout.align(width);
+                layout.push(&source, bytes.len() as u32, StatementKind::Bytes(bytes))?;
+            }
+            ".ascii" | ".asciiz" => {
+                let [literal] = operands.as_slice() else {
+                    return Err(error(format!("'{mnemonic}' takes one string")));
+                };
+                let mut bytes =
+                    parse_string(literal).map_err(|message| error_at(literal, message))?;
+                if mnemonic == ".asciiz" {
+                    bytes.push(0);
+                }
+                layout.push(&source, bytes.len() as u32, StatementKind::Bytes(bytes))?;
+            }
+            ".space" => {
+                let [size] = operands.as_slice() else {
+                    return Err(error("'.space' takes one size".to_owned()));
+                };
+                let bytes = u32::try_from(number(size)?)
+                    .map_err(|_| error_at(size, format!("'.space {size}' is too large")))?;
+                layout.push(&source, bytes, StatementKind::Space)?;
+            }
+            ".align" => {
+                let [power] = operands.as_slice() else {
+                    return Err(error("'.align' takes one power of two".to_owned()));
+                };
+                match number(power)? {
+                    power @ 0..=12 => layout.align(1 << power),
+                    too_large => {
+                        return Err(error_at(
+                            power,
+                            format!("'.align {too_large}' is too large"),
+                        ))
+                    }
+                }
+            }
+            // Every label is visible to the whole program, so there is nothing to export
+            ".globl" | ".global" => {}
+            ".eqv" => {
+                // Read the name as written, since substitution replaces one
+                // that is already defined
+                let (name, value) = written
+                    .split_once(char::is_whitespace)
+                    .and_then(|(_, operands)| operands.trim().split_once(char::is_whitespace))
+                    .ok_or_else(|| error("'.eqv' needs a name and a value".to_owned()))?;
+                if !is_identifier(name) {
+                    return Err(error_at(name, format!("invalid .eqv name '{name}'")));
+                }
+                if layout.eqvs.contains_key(name) {
+                    return Err(error_at(name, format!(".eqv '{name}' is already defined")));
+                }
+                let value = substitute_eqvs(value.trim(), &layout.eqvs);
+                layout.eqvs.insert(name.to_owned(), value);
+            }
+            _ => return Err(error(format!("unknown directive '{mnemonic}'"))),
+        }
+    }
+    layout.bind_labels()?;
+
+    // Pass 2: encode with every label known
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut pseudo_sources = BTreeMap::new();
+    for statement in &layout.statements {
+        let bytes = match &statement.kind {
+            StatementKind::Instruction { mnemonic, operands } => {
+                let address_of = |text: &str| {
+                    resolve(text, &layout.labels).ok_or_else(|| {
+                        statement.error_at(text, format!("undefined label '{text}'"))
+                    })
+                };
+                let expansion = expand(&statement.source, mnemonic, operands, &address_of)?;
+                let Some(basics) = expansion else {
+                    let word = encode(
+                        statement,
+                        statement.address,
+                        mnemonic,
+                        operands,
+                        &layout.labels,
+                    )?;
+                    emit(&mut segments, statement, word_bytes(word, 4, endianness));
+                    continue;
+                };
+                let source = format!("{mnemonic} {}", operands.join(", "));
+                let mut bytes = Vec::new();
+                for (i, (basic, basic_operands)) in basics.iter().enumerate() {
+                    let address = statement.address.wrapping_add(4 * i as u32);
+                    let word = encode(statement, address, basic, basic_operands, &layout.labels)?;
+                    bytes.extend(word_bytes(word, 4, endianness));
+                    pseudo_sources.insert(address, source.trim_end().to_owned());
+                }
+                bytes
+            }
+            StatementKind::Values { width, values } => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    let value = data_value(statement, value, *width, &layout.labels)?;
+                    bytes.extend(word_bytes(value, *width, endianness));
+                }
+                bytes
+            }
+            StatementKind::Bytes(bytes) => bytes.clone(),
+            StatementKind::Space => continue,
+        };
+        emit(&mut segments, statement, bytes);
+    }
+
+    let entry = layout.labels.get("main").copied().unwrap_or_else(|| {
+        segments
+            .iter()
+            .find(|s| s.kind == SegmentKind::Text)
+            .map_or(TEXT_BASE, |s| s.address)
+    });
+    Ok(Program {
+        segments,
+        entry,
+        endianness,
+        labels: layout.labels,
+        pseudo_sources,
+        delay_slots: false,
+    })
+}
+
+/// Append a statement's bytes to its segment, starting a new one after a jump
+fn emit(segments: &mut Vec<Segment>, statement: &Statement, bytes: Vec<u8>) {
+    // Alignment padding is filled in; bigger gaps (a new `.data ADDR`) start a new segment
+    const MAX_PADDING: u32 = 1 << 12;
+
+    let current = segments
+        .iter_mut()
+        .rev()
+        .find(|s| s.kind == statement.segment);
+    if let Some(segment) = current {
+        let end = segment.address.wrapping_add(segment.bytes.len() as u32);
+        let gap = statement.address.wrapping_sub(end);
+        if gap <= MAX_PADDING {
+            segment.bytes.resize(segment.bytes.len() + gap as usize, 0);
+            segment.bytes.extend(bytes);
+            return;
+        }
+    }
+    segments.push(Segment {
+        kind: statement.segment,
+        address: statement.address,
+        bytes,
+    });
+}
+
+/// The `width` low bytes of `value` in memory order
+fn word_bytes(value: u32, width: u32, endianness: Endianness) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut bytes = bytes[(4 - width as usize)..].to_vec();
+    if endianness == Endianness::Little {
+        bytes.reverse();
+    }
+    bytes
+}
+
+/// Resolve one `.word`/`.half`/`.byte` value: a label or an integer that fits `width`
+fn data_value(
+    statement: &Statement,
+    text: &str,
+    width: u32,
+    labels: &HashMap<String, u32>,
+) -> Result<u32, AsmError> {
+    if let Some(&address) = labels.get(text) {
+        if width == 4 {
+            return Ok(address);
+        }
+        return Err(statement.error_at(
+            text,
+            format!("label '{text}' does not fit in {width} byte(s)"),
+        ));
+    }
+    let value = parse_int(text).ok_or_else(|| {
+        let what = if is_identifier(text) {
+            "undefined label"
+        } else {
+            "invalid value"
+        };
+        statement.error_at(text, format!("{what} '{text}'"))
+    })?;
+    let bits = width * 8;
+    let min = -(1i64 << (bits - 1));
+    let max = (1i64 << bits) - 1;
+    if value < min || value > max {
+        return Err(statement.error_at(
+            text,
+            format!("value {value} does not fit in {width} byte(s)"),
+        ));
+    }
+    Ok(value as u32)
+}
+
+/// Replace whole identifiers defined by `.eqv`, leaving strings alone
+fn substitute_eqvs(text: &str, eqvs: &HashMap<String, String>) -> String {
+    if eqvs.is_empty() {
+        return text.to_owned();
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    let mut in_string = false;
+    while let Some((start, c)) = chars.next() {
+        if c == '"' {
+            in_string = !in_string;
+        }
+        let starts_identifier = c.is_ascii_alphabetic() || c == '_';
+        if in_string || !starts_identifier {
+            result.push(c);
+            if in_string && c == '\\' {
+                if let Some((_, escaped)) = chars.next() {
+                    result.push(escaped);
+                }
+            }
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, next)) = chars.peek() {
+            if !(next.is_ascii_alphanumeric() || next == '_' || next == '.') {
+                break;
+            }
+            end = i + next.len_utf8();
+            chars.next();
+        }
+        let word = &text[start..end];
+        result.push_str(eqvs.get(word).map_or(word, String::as_str));
+    }
+    result
+}
+
+/// Split an operand list on commas that are not inside a string or character literal
+fn split_operands(text: &str) -> Vec<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ',' => {
+                operands.push(current.trim().to_owned());
+                current.clear();
+                continue;
+            }
+            None => {}
+        }
+        current.push(c);
+    }
+    operands.push(current.trim().to_owned());
+    operands
+}
+
+/// Decode one escape sequence's character (after the backslash)
+fn unescape(c: char) -> Option<u8> {
+    Some(match c {
+        'n' => b'\n',
+        't' => b'\t',
+        'r' => b'\r',
+        '0' => 0,
+        '\\' => b'\\',
+        '"' => b'"',
+        '\'' => b'\'',
+        _ => return None,
+    })
+}
+
+/// Parse a double-quoted string literal into bytes
+fn parse_string(literal: &str) -> Result<Vec<u8>, String> {
+    let inner = literal
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, found '{literal}'"))?;
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let escaped = chars.next().and_then(unescape);
+            bytes.push(escaped.ok_or_else(|| format!("invalid escape in {literal}"))?);
+        } else if c.is_ascii() {
+            bytes.push(c as u8);
+        } else {
+            return Err(format!("non-ASCII character '{c}' in string"));
+        }
+    }
+    Ok(bytes)
+}
+
+/// Drop everything after a `#` that is not inside a string or character literal
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (c, quote) {
+            ('\\', Some(_)) => escaped = true,
+            (c, Some(q)) if c == q => quote = None,
+            ('"' | '\'', None) => quote = Some(c),
+            ('#', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Parse a decimal, `0x` hexadecimal or `0b` binary integer, optionally
+/// negative, or a character literal such as `'A'` or `'\n'`
+fn parse_int(text: &str) -> Option<i64> {
+    if let Some(inner) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        let mut chars = inner.chars();
+        let value = match (chars.next()?, chars.next()) {
+            ('\\', Some(escaped)) => unescape(escaped)?,
+            (c, None) if c.is_ascii() => c as u8,
+            _ => return None,
+        };
+        return chars.next().is_none().then_some(value as i64);
+    }
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// The label's address, or the value of an integer literal
+fn resolve(text: &str, labels: &HashMap<String, u32>) -> Option<u32> {
+    labels
+        .get(text)
+        .copied()
+        .or_else(|| parse_int(text).map(|v| v as u32))
+}
+
+/// A real instruction produced by expanding a pseudo-instruction
+type Basic = (&'static str, Vec<String>);
+
+fn basic(mnemonic: &'static str, operands: &[&str]) -> Basic {
+    (mnemonic, operands.iter().map(|&op| op.to_owned()).collect())
+}
+
+/// Put `value` in `$at` the way MARS does: `addi` if it fits in 16 signed
+/// bits, otherwise `lui` + `ori`
+fn load_at(value: u32) -> Vec<Basic> {
+    if (-0x8000..=0x7FFF).contains(&(value as i32)) {
+        vec![basic(
+            "addi",
+            &["$at", "$zero", &(value as i32).to_string()],
+        )]
+    } else {
+        vec![
+            basic("lui", &["$at", &format!("0x{:X}", value >> 16)]),
+            basic("ori", &["$at", "$at", &format!("0x{:X}", value & 0xFFFF)]),
+        ]
+    }
+}
+
+/// Expand a pseudo-instruction into the real instructions MARS emits for it,
+/// or return `None` if `mnemonic` is not a pseudo-instruction
+///
+/// `address_of` resolves `la` operands; during layout it returns a placeholder
+/// since only the number of instructions matters there.
+fn expand(
+    source: &SourceLine,
+    mnemonic: &str,
+    operands: &[String],
+    address_of: &dyn Fn(&str) -> Result<u32, AsmError>,
+) -> Result<Option<Vec<Basic>>, AsmError> {
+    let ops: Vec<&str> = operands.iter().map(String::as_str).collect();
+    let arity = |count: usize| {
+        if ops.len() == count {
+            Ok(())
+        } else {
+            Err(source.error(format!(
+                "'{mnemonic}' expects {count} operand(s), found {}",
+                ops.len()
+            )))
+        }
+    };
+    let int = |text: &str| {
+        let value = parse_int(text)
+            .ok_or_else(|| source.error_at(text, format!("invalid integer '{text}'")))?;
+        if !(-(1 << 31)..=u32::MAX as i64).contains(&value) {
+            return Err(source.error_at(text, format!("immediate {value} does not fit in 32 bits")));
+        }
+        Ok(value as u32)
+    };
+    let is_register = |text: &str| isa::parse_register(text).is_some();
+
+    let basics = match mnemonic {
+        "nop" => {
+            arity(0)?;
+            vec![basic("sll", &["$zero", "$zero", "0"])]
+        }
+        "move" => {
+            arity(2)?;
+            vec![basic("addu", &[ops[0], "$zero", ops[1]])]
+        }
+        "not" => {
+            arity(2)?;
+            vec![basic("nor", &[ops[0], ops[1], "$zero"])]
+        }
+        "neg" => {
+            arity(2)?;
+            vec![basic("sub", &[ops[0], "$zero", ops[1]])]
+        }
+        "li" => {
+            arity(2)?;
+            let value = int(ops[1])?;
+            if (-0x8000..=0x7FFF).contains(&(value as i32)) {
+                vec![basic(
+                    "addiu",
+                    &[ops[0], "$zero", &(value as i32).to_string()],
+                )]
+            } else if value <= 0xFFFF {
+                vec![basic("ori", &[ops[0], "$zero", &format!("0x{value:X}")])]
+            } else {
+                vec![
+                    basic("lui", &["$at", &format!("0x{:X}", value >> 16)]),
+                    basic("ori", &[ops[0], "$at", &format!("0x{:X}", value & 0xFFFF)]),
+                ]
+            }
+        }
+        "la" => {
+            arity(2)?;
+            let address = address_of(ops[1])?;
+            vec![
+                basic("lui", &["$at", &format!("0x{:X}", address >> 16)]),
+                basic(
+                    "ori",
+                    &[ops[0], "$at", &format!("0x{:X}", address & 0xFFFF)],
+                ),
+            ]
+        }
+        // The register form of mul is a real SPECIAL2 instruction
+        "mul" if ops.len() == 3 && !is_register(ops[2]) => {
+            let mut basics = load_at(int(ops[2])?);
+            basics.push(basic("mul", &[ops[0], ops[1], "$at"]));
+            basics
+        }
+        "blt" | "bgt" | "ble" | "bge" => {
+            arity(3)?;
+            // blt/bge branch on rs < rt, bgt/ble on rt < rs
+            let swapped = matches!(mnemonic, "bgt" | "ble");
+            let branch = if matches!(mnemonic, "blt" | "bgt") {
+                "bne"
+            } else {
+                "beq"
+            };
+            let mut basics = if is_register(ops[1]) {
+                let (lhs, rhs) = if swapped {
+                    (ops[1], ops[0])
+                } else {
+                    (ops[0], ops[1])
+                };
+                vec![basic("slt", &["$at", lhs, rhs])]
+            } else {
+                let value = int(ops[1])?;
+                if !swapped && (-0x8000..=0x7FFF).contains(&(value as i32)) {
+                    vec![basic("slti", &["$at", ops[0], &(value as i32).to_string()])]
+                } else {
+                    let mut basics = load_at(value);
+                    let (lhs, rhs) = if swapped {
+                        ("$at", ops[0])
+                    } else {
+                        (ops[0], "$at")
+                    };
+                    basics.push(basic("slt", &["$at", lhs, rhs]));
+                    basics
+                }
+            };
+            basics.push(basic(branch, &["$at", "$zero", ops[2]]));
+            basics
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(basics))
+}
+
+fn encode_r(opcode: u32, rs: usize, rt: usize, rd: usize, shamt: u32, funct: u32) -> u32 {
+    (opcode << 26)
+        | ((rs as u32) << 21)
+        | ((rt as u32) << 16)
+        | ((rd as u32) << 11)
+        | ((shamt & 0x1F) << 6)
+        | (funct & 0x3F)
+}
+
+fn encode_i(opcode: u32, rs: usize, rt: usize, imm: u32) -> u32 {
+    (opcode << 26) | ((rs as u32) << 21) | ((rt as u32) << 16) | (imm & 0xFFFF)
+}
+
+fn encode_j(opcode: u32, target: u32) -> u32 {
+    (opcode << 26) | (target & 0x03FF_FFFF)
+}
+
+/// Encode one real instruction placed at `address`
+fn encode(
+    statement: &Statement,
+    address: u32,
+    mnemonic: &str,
+    operands: &[String],
+    labels: &HashMap<String, u32>,
+) -> Result<u32, AsmError> {
+    let spec: &InstrSpec = isa::find_instruction(mnemonic)
+        .ok_or_else(|| statement.error(format!("unknown mnemonic '{mnemonic}'")))?;
+    let ops: Vec<&str> = operands.iter().map(String::as_str).collect();
+
+    let expected = match spec.operands {
+        Operands::RdRsRt | Operands::RdRtShamt | Operands::RdRtRs => 3,
+        Operands::RtRsSimm | Operands::RtRsImm | Operands::Branch2 => 3,
+        Operands::RsRt | Operands::RdRs | Operands::RtImm | Operands::Memory => 2,
+        Operands::CopMove | Operands::FdFs | Operands::RtFs | Operands::FpMemory => 2,
+        Operands::FdFsFt => 3,
+        // The condition flag defaults to 0
+        Operands::FsFt if ops.len() == 3 => 3,
+        Operands::FsFt => 2,
+        Operands::Bc1 if ops.len() == 2 => 2,
+        Operands::Bc1 => 1,
+        Operands::Branch1 => 2,
+        Operands::Rd | Operands::Rs | Operands::Jump => 1,
+        Operands::Jalr if ops.len() == 2 => 2,
+        Operands::Jalr => 1,
+        Operands::None => 0,
+    };
+    if ops.len() != expected {
+        return Err(statement.error(format!(
+            "'{}' expects {expected} operand(s), found {}",
+            spec.mnemonic,
+            ops.len()
+        )));
+    }
+
+    let reg = |text: &str| {
+        isa::parse_register(text)
+            .ok_or_else(|| statement.error_at(text, format!("invalid register '{text}'")))
+    };
+    // Doubles occupy even/odd register pairs named by the even register
+    let (double_result, double_sources) = spec.fp_doubles();
+    let freg = |text: &str, double: bool| {
+        let index = cp1::parse_fp_register(text).ok_or_else(|| {
+            statement.error_at(text, format!("invalid floating-point register '{text}'"))
+        })?;
+        if double && index % 2 != 0 {
+            return Err(statement.error_at(
+                text,
+                format!(
+                    "'{}' needs an even-numbered register, found '{text}'",
+                    spec.mnemonic
+                ),
+            ));
+        }
+        Ok(index)
+    };
+    let int = |text: &str, min: i64, max: i64| {
+        let value = parse_int(text)
+            .ok_or_else(|| statement.error_at(text, format!("invalid integer '{text}'")))?;
+        if value < min || value > max {
+            return Err(statement.error_at(
+                text,
+                format!("immediate {value} is out of range {min}..={max}"),
+            ));
+        }
+        Ok(value as u32)
+    };
+    let address_of = |text: &str| {
+        resolve(text, labels)
+            .ok_or_else(|| statement.error_at(text, format!("undefined label '{text}'")))
+    };
+    // `offset(base)`, `(base)` or a bare offset from $zero
+    let memory_operand = |text: &str| {
+        let (offset, base) = match text.split_once('(') {
+            Some((offset, base)) => {
+                let base = base
+                    .strip_suffix(')')
+                    .ok_or_else(|| statement.error_at(text, "expected ')' after base register"))?;
+                (offset.trim(), reg(base.trim())?)
+            }
+            None => (text, 0),
+        };
+        let offset = if offset.is_empty() {
+            0
+        } else {
+            int(offset, -0x8000, 0x7FFF)?
+        };
+        Ok((offset, base))
+    };
+    let branch_offset = |text: &str| {
+        let target = address_of(text)?;
+        let offset = (target.wrapping_sub(address.wrapping_add(4)) as i32) >> 2;
+        if !(-0x8000..=0x7FFF).contains(&offset) {
+            return Err(statement.error_at(text, format!("branch target '{text}' is too far away")));
+        }
+        Ok(offset as u32)
+    };
+
+    let word = match spec.operands {
+        Operands::RdRsRt => encode_r(
+            spec.opcode,
+            reg(ops[1])?,
+            reg(ops[2])?,
+            reg(ops[0])?,
+            0,
+            spec.funct,
+        ),
+        Operands::RdRtShamt => encode_r(
+            spec.opcode,
+            0,
+            reg(ops[1])?,
+            reg(ops[0])?,
+            int(ops[2], 0, 31)?,
+            spec.funct,
+        ),
+        Operands::RdRtRs => encode_r(
+            spec.opcode,
+            reg(ops[2])?,
+            reg(ops[1])?,
+            reg(ops[0])?,
+            0,
+            spec.funct,
+        ),
+        Operands::RsRt => encode_r(spec.opcode, reg(ops[0])?, reg(ops[1])?, 0, 0, spec.funct),
+        Operands::RdRs => {
+            // clz/clo encode rd in both the rd and rt fields
+            let rd = reg(ops[0])?;
+            encode_r(spec.opcode, reg(ops[1])?, rd, rd, 0, spec.funct)
+        }
+        Operands::Rd => encode_r(spec.opcode, 0, 0, reg(ops[0])?, 0, spec.funct),
+        Operands::Rs => encode_r(spec.opcode, reg(ops[0])?, 0, 0, 0, spec.funct),
+        Operands::Jalr => {
+            let (rd, rs) = match ops.as_slice() {
+                [rd, rs] => (reg(rd)?, reg(rs)?),
+                _ => (31, reg(ops[0])?),
+            };
+            encode_r(spec.opcode, rs, 0, rd, 0, spec.funct)
+        }
+        Operands::None if spec.opcode == isa::op::COP0 => {
+            encode_r(spec.opcode, spec.funct as usize, 0, 0, 0, isa::cop0::ERET)
+        }
+        Operands::None => encode_r(spec.opcode, 0, 0, 0, 0, spec.funct),
+        Operands::RtRsSimm => encode_i(
+            spec.opcode,
+            reg(ops[1])?,
+            reg(ops[0])?,
+            int(ops[2], -0x8000, 0x7FFF)?,
+        ),
+        Operands::RtRsImm => encode_i(
+            spec.opcode,
+            reg(ops[1])?,
+            reg(ops[0])?,
+            int(ops[2], 0, 0xFFFF)?,
+        ),
+        Operands::RtImm => encode_i(spec.opcode, 0, reg(ops[0])?, int(ops[1], 0, 0xFFFF)?),
+        Operands::Memory => {
+            let (offset, base) = memory_operand(ops[1])?;
+            encode_i(spec.opcode, base, reg(ops[0])?, offset)
+        }
+        Operands::FpMemory => {
+            let (offset, base) = memory_operand(ops[1])?;
+            encode_i(spec.opcode, base, freg(ops[0], double_result)?, offset)
+        }
+        Operands::Branch2 => encode_i(
+            spec.opcode,
+            reg(ops[0])?,
+            reg(ops[1])?,
+            branch_offset(ops[2])?,
+        ),
+        Operands::Branch1 => {
+            // REGIMM branches select the condition through the rt field
+            let rt = if spec.opcode == isa::op::REGIMM {
+                spec.funct as usize
+            } else {
+                0
+            };
+            encode_i(spec.opcode, reg(ops[0])?, rt, branch_offset(ops[1])?)
+        }
+        Operands::CopMove => {
+            let number = ops[1]
+                .strip_prefix('$')
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|&n| n < 32)
+                .ok_or_else(|| {
+                    statement.error_at(ops[1], format!("invalid coprocessor register '{}'", ops[1]))
+                })?;
+            let selector = if spec.opcode == isa::op::COP1 {
+                spec.fmt
+            } else {
+                spec.funct
+            };
+            encode_r(spec.opcode, selector as usize, reg(ops[0])?, number, 0, 0)
+        }
+        Operands::FdFsFt => encode_r(
+            spec.opcode,
+            spec.fmt as usize,
+            freg(ops[2], double_sources)?,
+            freg(ops[1], double_sources)?,
+            freg(ops[0], double_result)? as u32,
+            spec.funct,
+        ),
+        Operands::FdFs => encode_r(
+            spec.opcode,
+            spec.fmt as usize,
+            0,
+            freg(ops[1], double_sources)?,
+            freg(ops[0], double_result)? as u32,
+            spec.funct,
+        ),
+        Operands::FsFt => {
+            let (cc, fs, ft) = match ops.as_slice() {
+                [cc, fs, ft] => (int(cc, 0, 7)?, fs, ft),
+                _ => (0, &ops[0], &ops[1]),
+            };
+            encode_r(
+                spec.opcode,
+                spec.fmt as usize,
+                freg(ft, double_sources)?,
+                freg(fs, double_sources)?,
+                cc << 2,
+                spec.funct,
+            )
+        }
+        Operands::RtFs => encode_r(
+            spec.opcode,
+            spec.fmt as usize,
+            reg(ops[0])?,
+            freg(ops[1], false)?,
+            0,
+            0,
+        ),
+        Operands::Bc1 => {
+            let (cc, label) = match ops.as_slice() {
+                [cc, label] => (int(cc, 0, 7)?, label),
+                _ => (0, &ops[0]),
+            };
+            let rt = (cc << 2 | spec.funct) as usize;
+            encode_i(spec.opcode, spec.fmt as usize, rt, branch_offset(label)?)
+        }
+        Operands::Jump => {
+            let target = address_of(ops[0])?;
+            if target & 0xF000_0000 != address.wrapping_add(4) & 0xF000_0000 {
+                return Err(statement.error_at(
+                    ops[0],
+                    format!(
+                        "jump target '{}' is outside the current 256 MB region",
+                        ops[0]
+                    ),
+                ));
+            }
+            encode_j(spec.opcode, target >> 2)
+        }
+    };
+    Ok(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> AsmError {
+        match assemble(source, Endianness::Big) {
+            Ok(_) => panic!("assembly should fail"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn error_after_lengthening_eqv_points_at_written_text() {
+        let e = error(".eqv L verylonglabelname\n.text\nL: bogus $t0\n");
+        assert_eq!((e.line, e.column, e.length), (3, 4, 5));
+        let e = error(".eqv L verylonglabelname\n.text\n  L:  M: bogus $t0\n");
+        assert_eq!((e.line, e.column), (3, 10));
+    }
+
+    #[test]
+    fn space_reserves_without_storing() {
+        let program = assemble(
+            ".data\nbuffer: .space 1000000\nafter: .word 7\n",
+            Endianness::Big,
+        )
+        .expect("program assembles");
+        assert_eq!(program.labels["after"], DATA_BASE + 1_000_000);
+        let stored: usize = program.segments.iter().map(|s| s.bytes.len()).sum();
+        assert!(stored < 4096, "{stored} bytes stored");
+    }
+
+    #[test]
+    fn redefining_an_eqv_is_an_error() {
+        let e = error(".eqv N 5\n.eqv N 6\n");
+        assert_eq!((e.line, e.column, e.length), (2, 6, 1));
+        assert_eq!(e.message, ".eqv 'N' is already defined");
+        // Values may still use earlier names
+        let program = assemble(
+            ".eqv N 5\n.eqv M N\n.data\nvalue: .word M\n",
+            Endianness::Big,
+        )
+        .expect("program assembles");
+        assert_eq!(program.segments[0].bytes, [0, 0, 0, 5]);
+    }
+
+    #[test]
+    fn space_past_the_segment_is_an_error() {
+        let e = error(".data\n.space 4000000000\n");
+        assert_eq!((e.line, e.column), (2, 1));
+        assert!(e.message.contains("past the end of the .data segment"));
+        let e = error(".text\n.space 0x0FC00001\n");
+        assert!(e.message.contains(".text"));
+        assert!(assemble(".text\n.space 0x0FC00000\n", Endianness::Big).is_ok());
+    }
+
+    #[test]
+    fn data_past_the_segment_is_an_error() {
+        let e = error(".kdata 0xfffefffc\n.word 1, 2, 3\n");
+        assert_eq!(e.message, "'.word' runs past the end of the .kdata segment");
+        let e = error(".data 0x7ffffffe\n.asciiz \"ab\"\n");
+        assert!(e.message.contains(".data segment"));
+        assert!(assemble(".kdata 0xfffefffc\n.word 1\n", Endianness::Big).is_ok());
+    }
+
+    /// The first word `source` assembles to in `.text`
+    fn first_word(source: &str) -> u32 {
+        let program = assemble(&format!(".text\n{source}\n"), Endianness::Big)
+            .unwrap_or_else(|e| panic!("'{source}': {}", e.message));
+        let bytes = &program.segments[0].bytes;
+        u32::from_be_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    #[test]
+    fn every_instruction_survives_disassembly() {
+        for spec in isa::INSTRUCTIONS {
+            let operands = match spec.operands {
+                Operands::RdRsRt | Operands::RdRtRs => "$t0, $t1, $t2",
+                Operands::RdRtShamt => "$t0, $t1, 5",
+                Operands::RsRt | Operands::RdRs | Operands::Jalr => "$t1, $t2",
+                Operands::Rd | Operands::Rs => "$t1",
+                Operands::None => "",
+                Operands::RtRsSimm => "$t0, $t1, -5",
+                Operands::RtRsImm => "$t0, $t1, 0x00FF",
+                Operands::RtImm => "$t0, 0x1234",
+                Operands::Memory => "$t0, -8($sp)",
+                Operands::Branch2 => "$t0, $t1, 0x00400020",
+                Operands::Branch1 => "$t0, 0x00400020",
+                Operands::Jump => "0x00400020",
+                Operands::CopMove => "$t0, $12",
+                Operands::FdFsFt => "$f2, $f4, $f6",
+                Operands::FdFs => "$f2, $f4",
+                Operands::FsFt => "2, $f4, $f6",
+                Operands::RtFs => "$t0, $f4",
+                Operands::FpMemory => "$f2, 8($sp)",
+                Operands::Bc1 => "1, 0x00400020",
+            };
+            let source = format!("{} {operands}", spec.mnemonic);
+            let word = first_word(&source);
+            assert_eq!(
+                isa::decode(word).map(|s| s.mnemonic),
+                Some(spec.mnemonic),
+                "{source}"
+            );
+            let text = crate::disasm::disassemble(word, TEXT_BASE).expect("word disassembles");
+            assert_eq!(
+                first_word(&text),
+                word,
+                "'{source}' disassembled to '{text}'"
+            );
+        }
+    }
+
+    /// Disassembly of every word `source` assembles to in `.text`
+    fn listing(source: &str) -> Vec<String> {
+        let program = assemble(source, Endianness::Big).expect("program assembles");
+        let text = program
+            .segments
+            .iter()
+            .find(|s| s.kind == SegmentKind::Text)
+            .unwrap();
+        text.bytes
+            .chunks(4)
+            .zip((text.address..).step_by(4))
+            .map(|(bytes, address)| {
+                let word = u32::from_be_bytes(bytes.try_into().unwrap());
+                crate::disasm::disassemble(word, address).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn li_picks_the_shortest_expansion() {
+        assert_eq!(listing("li $t0, 5"), ["addiu $t0, $zero, 5"]);
+        assert_eq!(listing("li $t0, -1"), ["addiu $t0, $zero, -1"]);
+        assert_eq!(listing("li $t0, 0xFFFF"), ["ori $t0, $zero, 0xFFFF"]);
+        assert_eq!(
+            listing("li $t0, 0x12345678"),
+            ["lui $at, 0x1234", "ori $t0, $at, 0x5678"]
+        );
+    }
+
+    #[test]
+    fn la_loads_a_label_address() {
+        let source = ".data\n.word 0\nvalue: .word 1\n.text\nla $a0, value\n";
+        assert_eq!(listing(source), ["lui $at, 0x1001", "ori $a0, $at, 0x0004"]);
+    }
+
+    #[test]
+    fn mul_with_an_immediate_goes_through_at() {
+        assert_eq!(listing("mul $t0, $t1, $t2"), ["mul $t0, $t1, $t2"]);
+        assert_eq!(
+            listing("mul $t0, $t1, 7"),
+            ["addi $at, $zero, 7", "mul $t0, $t1, $at"]
+        );
+    }
+
+    #[test]
+    fn blt_compares_into_at() {
+        assert_eq!(
+            listing("loop: blt $t0, $t1, loop"),
+            ["slt $at, $t0, $t1", "bne $at, $zero, 0x00400000"]
+        );
+        assert_eq!(
+            listing("loop: blt $t0, 3, loop"),
+            ["slti $at, $t0, 3", "bne $at, $zero, 0x00400000"]
+        );
+        assert_eq!(
+            listing("loop: bgt $t0, $t1, loop"),
+            ["slt $at, $t1, $t0", "bne $at, $zero, 0x00400000"]
+        );
+        assert_eq!(
+            listing("loop: bge $t0, $t1, loop"),
+            ["slt $at, $t0, $t1", "beq $at, $zero, 0x00400000"]
+        );
+    }
+
+    #[test]
+    fn expanded_words_remember_their_pseudo_instruction() {
+        let program =
+            assemble("li $t0, 0x12345678\naddu $t0, $t0, $t0\n", Endianness::Big).unwrap();
+        let source = |address: u32| program.pseudo_sources.get(&address).map(String::as_str);
+        assert_eq!(source(TEXT_BASE), Some("li $t0, 0x12345678"));
+        assert_eq!(source(TEXT_BASE + 4), Some("li $t0, 0x12345678"));
+        assert_eq!(source(TEXT_BASE + 8), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! Bitmap display: a region of memory shown as a grid of RGB pixels, as in MARS
+//!
+//! Each word holds one unit (a block of `unit_width` × `unit_height` screen
+//! pixels) as `0x00RRGGBB`, row by row from the base address.
+
+use crate::assembler::DATA_BASE;
+use crate::cpu::GLOBAL_POINTER;
+use crate::memory::Memory;
+use crate::syscall::HEAP_BASE;
+
+/// Base addresses offered for the display, with what lives there
+pub const BASE_ADDRESSES: [(u32, &str); 4] = [
+    (0x1000_0000, "global data"),
+    (GLOBAL_POINTER, "$gp"),
+    (DATA_BASE, "static data"),
+    (HEAP_BASE, "heap"),
+];
+
+/// Sizes in screen pixels offered for units and for the whole display
+pub const UNIT_SIZES: [u32; 6] = [1, 2, 4, 8, 16, 32];
+pub const DISPLAY_SIZES: [u32; 5] = [64, 128, 256, 512, 1024];
+
+/// Display geometry and where its pixels live
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct BitmapConfig {
+    pub unit_width: u32,
+    pub unit_height: u32,
+    pub display_width: u32,
+    pub display_height: u32,
+    pub base: u32,
+}
+
+impl Default for BitmapConfig {
+    fn default() -> Self {
+        Self {
+            unit_width: 8,
+            unit_height: 8,
+            display_width: 512,
+            display_height: 256,
+            base: DATA_BASE,
+        }
+    }
+}
+
+impl BitmapConfig {
+    /// Units per row
+    pub fn columns(&self) -> u32 {
+        (self.display_width / self.unit_width.max(1)).max(1)
+    }
+
+    /// Rows of units
+    pub fn rows(&self) -> u32 {
+        (self.display_height / self.unit_height.max(1)).max(1)
+    }
+
+    /// Address of the word holding unit (`column`, `row`)
+    pub fn address(&self, column: u32, row: u32) -> u32 {
+        let index = row.wrapping_mul(self.columns()).wrapping_add(column);
+        self.base.wrapping_add(index.wrapping_mul(4))
+    }
+
+    /// Last address the display reads
+    pub fn end(&self) -> u32 {
+        self.address(self.columns() - 1, self.rows() - 1)
+            .wrapping_add(3)
+    }
+
+    /// `[r, g, b]` of every unit, row by row
+    pub fn pixels(&self, memory: &Memory) -> Vec<[u8; 3]> {
+        let mut pixels = Vec::with_capacity((self.columns() * self.rows()) as usize);
+        for row in 0..self.rows() {
+            for column in 0..self.columns() {
+                let [_, r, g, b] = memory.read_word(self.address(column, row)).to_be_bytes();
+                pixels.push([r, g, b]);
+            }
+        }
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_come_from_words_row_by_row() {
+        let config = BitmapConfig {
+            unit_width: 16,
+            unit_height: 32,
+            display_width: 64,
+            display_height: 128,
+            base: 0x1000_8000,
+        };
+        assert_eq!((config.columns(), config.rows()), (4, 4));
+        assert_eq!(config.address(0, 0), 0x1000_8000);
+        assert_eq!(config.address(3, 2), 0x1000_8000 + (2 * 4 + 3) * 4);
+        assert_eq!(config.end(), 0x1000_8000 + 16 * 4 - 1);
+
+        let mut memory = Memory::new();
+        memory.write_word(config.address(1, 0), 0x00FF_8000);
+        // The top byte is ignored
+        memory.write_word(config.address(3, 2), 0xAB12_3456);
+        let pixels = config.pixels(&memory);
+        assert_eq!(pixels.len(), 16);
+        assert_eq!(pixels[1], [0xFF, 0x80, 0x00]);
+        assert_eq!(pixels[2 * 4 + 3], [0x12, 0x34, 0x56]);
+        let lit = pixels.iter().filter(|&&pixel| pixel != [0; 3]).count();
+        assert_eq!(lit, 2);
+    }
+}
@@ -0,0 +1,453 @@
+//! Statistics-only model of a set-associative cache
+//!
+//! The cache is not on the path between the core and memory: the machine
+//! shows it each fetch and data access after memory has served it, and it
+//! only tracks which blocks it would hold so it can count hits, misses and
+//! memory writes. Memory stays the single source of truth for data.
+
+/// Which line of a full set gets evicted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Replacement {
+    /// Least recently used
+    #[default]
+    Lru,
+    /// Oldest fill first
+    Fifo,
+    Random,
+}
+
+impl Replacement {
+    pub const ALL: [Self; 3] = [Self::Lru, Self::Fifo, Self::Random];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lru => "LRU",
+            Self::Fifo => "FIFO",
+            Self::Random => "Random",
+        }
+    }
+}
+
+/// When stores reach memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum WritePolicy {
+    /// Dirty blocks are written when evicted; write misses allocate a line
+    #[default]
+    WriteBack,
+    /// Every store goes to memory; write misses do not allocate
+    WriteThrough,
+}
+
+impl WritePolicy {
+    pub const ALL: [Self; 2] = [Self::WriteBack, Self::WriteThrough];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::WriteBack => "Write-back",
+            Self::WriteThrough => "Write-through",
+        }
+    }
+}
+
+/// Largest cache the model accepts, in bytes
+const MAX_SIZE: u32 = 1 << 16;
+/// Smallest block: one word
+const MIN_BLOCK_SIZE: u32 = 4;
+
+/// Cache geometry and policies; sizes are in bytes and powers of two
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub size: u32,
+    pub block_size: u32,
+    /// Lines per set; equal to the number of blocks for a fully associative cache
+    pub associativity: u32,
+    pub replacement: Replacement,
+    pub write_policy: WritePolicy,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            size: 256,
+            block_size: 16,
+            associativity: 1,
+            replacement: Replacement::default(),
+            write_policy: WritePolicy::default(),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Round the sizes and associativity down to powers of two within the
+    /// ranges the model supports, so that addresses split into whole fields
+    pub fn normalized(self) -> Self {
+        let power_of_two = |n: u32| 1 << n.ilog2();
+        let size = power_of_two(self.size.clamp(MIN_BLOCK_SIZE, MAX_SIZE));
+        let block_size = power_of_two(self.block_size.clamp(MIN_BLOCK_SIZE, size));
+        let associativity = power_of_two(self.associativity.clamp(1, size / block_size));
+        Self {
+            size,
+            block_size,
+            associativity,
+            ..self
+        }
+    }
+
+    pub fn blocks(&self) -> u32 {
+        (self.size / self.block_size.max(4)).max(1)
+    }
+
+    pub fn sets(&self) -> u32 {
+        (self.blocks() / self.associativity.max(1)).max(1)
+    }
+
+    /// Lines per set, clamped so the cache never holds more than `blocks()`
+    pub fn ways(&self) -> u32 {
+        self.associativity.clamp(1, self.blocks())
+    }
+
+    pub fn offset_bits(&self) -> u32 {
+        self.block_size.max(4).trailing_zeros()
+    }
+
+    pub fn index_bits(&self) -> u32 {
+        self.sets().trailing_zeros()
+    }
+
+    pub fn tag_bits(&self) -> u32 {
+        32 - self.offset_bits() - self.index_bits()
+    }
+}
+
+/// One cache line's bookkeeping
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    pub valid: bool,
+    pub dirty: bool,
+    pub tag: u32,
+    /// Access counter values for LRU and FIFO
+    last_used: u64,
+    filled: u64,
+}
+
+/// A cache and its hit/miss counters
+///
+/// Only the configuration is persisted; contents start cold on every launch.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Cache {
+    #[serde(deserialize_with = "normalized_config")]
+    pub config: CacheConfig,
+    #[serde(skip)]
+    sets: Vec<Vec<Line>>,
+    /// Counts accesses, ordering lines for LRU and FIFO
+    #[serde(skip)]
+    clock: u64,
+    #[serde(skip)]
+    random_state: u32,
+    #[serde(skip)]
+    pub hits: u64,
+    #[serde(skip)]
+    pub misses: u64,
+    /// Stores written through plus dirty blocks written back
+    #[serde(skip)]
+    pub memory_writes: u64,
+}
+
+/// Persisted configurations may come from older versions or be edited by
+/// hand, so only geometry the model can split addresses by is accepted
+fn normalized_config<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<CacheConfig, D::Error> {
+    <CacheConfig as serde::Deserialize>::deserialize(deserializer).map(CacheConfig::normalized)
+}
+
+impl Cache {
+    /// Invalidate every line and clear the counters
+    pub fn reset(&mut self) {
+        *self = Self {
+            config: self.config,
+            ..Self::default()
+        };
+        self.allocate();
+    }
+
+    /// Whether `sets` matches the configured geometry
+    fn is_allocated(&self) -> bool {
+        self.sets.len() == self.config.sets() as usize
+            && self.sets[0].len() == self.config.ways() as usize
+    }
+
+    fn allocate(&mut self) {
+        let ways = self.config.ways() as usize;
+        self.sets = vec![vec![Line::default(); ways]; self.config.sets() as usize];
+    }
+
+    /// Lines grouped by set; empty until the first access after a reset
+    pub fn sets(&self) -> &[Vec<Line>] {
+        &self.sets
+    }
+
+    fn split(&self, address: u32) -> (usize, u32) {
+        let block = address >> self.config.offset_bits();
+        let set = block & (self.config.sets() - 1);
+        let tag = block >> self.config.index_bits();
+        (set as usize, tag)
+    }
+
+    /// First address of the block held by `line` in `set`
+    pub fn block_address(&self, set: usize, line: &Line) -> u32 {
+        let block = (line.tag << self.config.index_bits()) | set as u32;
+        block << self.config.offset_bits()
+    }
+
+    /// `(set, way)` of the valid line holding `address`, if cached
+    pub fn lookup(&self, address: u32) -> Option<(usize, usize)> {
+        if !self.config.enabled || !self.is_allocated() {
+            return None;
+        }
+        let (set, tag) = self.split(address);
+        let way = self.sets[set]
+            .iter()
+            .position(|line| line.valid && line.tag == tag)?;
+        Some((set, way))
+    }
+
+    /// Record a read or write of `address`; returns whether it hit, or
+    /// `None` when the cache is disabled
+    pub fn access(&mut self, address: u32, write: bool) -> Option<bool> {
+        if !self.config.enabled {
+            return None;
+        }
+        if !self.is_allocated() {
+            self.reset();
+        }
+        self.clock += 1;
+        let write_back = self.config.write_policy == WritePolicy::WriteBack;
+        if write && !write_back {
+            self.memory_writes += 1;
+        }
+
+        let (set, tag) = self.split(address);
+        if let Some(line) = self.sets[set]
+            .iter_mut()
+            .find(|line| line.valid && line.tag == tag)
+        {
+            line.last_used = self.clock;
+            line.dirty |= write && write_back;
+            self.hits += 1;
+            return Some(true);
+        }
+
+        self.misses += 1;
+        if write && !write_back {
+            // No write-allocate: the store went straight to memory
+            return Some(false);
+        }
+        let way = self.victim(set);
+        let line = &mut self.sets[set][way];
+        if line.valid && line.dirty {
+            self.memory_writes += 1;
+        }
+        *line = Line {
+            valid: true,
+            dirty: write && write_back,
+            tag,
+            last_used: self.clock,
+            filled: self.clock,
+        };
+        Some(false)
+    }
+
+    /// Way to fill in `set`: an invalid line if there is one, otherwise the
+    /// replacement policy's choice
+    fn victim(&mut self, set: usize) -> usize {
+        if let Some(way) = self.sets[set].iter().position(|line| !line.valid) {
+            return way;
+        }
+        let lines = &self.sets[set];
+        let oldest = |key: fn(&Line) -> u64| {
+            (0..lines.len())
+                .min_by_key(|&way| key(&lines[way]))
+                .unwrap_or(0)
+        };
+        match self.config.replacement {
+            Replacement::Lru => oldest(|line| line.last_used),
+            Replacement::Fifo => oldest(|line| line.filled),
+            Replacement::Random => {
+                let ways = lines.len();
+                self.next_random() as usize % ways
+            }
+        }
+    }
+
+    /// xorshift32, seeded on first use so a reset cache repeats its choices
+    fn next_random(&mut self) -> u32 {
+        let mut x = match self.random_state {
+            0 => 0x2545_F491,
+            state => state,
+        };
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random_state = x;
+        x
+    }
+
+    /// Fraction of accesses that hit, or 0 before the first access
+    pub fn hit_rate(&self) -> f64 {
+        let accesses = self.hits + self.misses;
+        if accesses == 0 {
+            0.0
+        } else {
+            self.hits as f64 / accesses as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: u32 = 0x1001_0000;
+    const B: u32 = 0x1001_0040;
+    const C: u32 = 0x1001_0080;
+
+    /// A two-line, fully associative cache of 16-byte blocks
+    fn cache(replacement: Replacement, write_policy: WritePolicy) -> Cache {
+        Cache {
+            config: CacheConfig {
+                enabled: true,
+                size: 32,
+                block_size: 16,
+                associativity: 2,
+                replacement,
+                write_policy,
+            },
+            ..Cache::default()
+        }
+    }
+
+    /// Hits, misses and memory writes after `accesses` of `(address, write)`
+    fn run(mut cache: Cache, accesses: &[(u32, bool)]) -> (u64, u64, u64) {
+        for &(address, write) in accesses {
+            cache.access(address, write);
+        }
+        (cache.hits, cache.misses, cache.memory_writes)
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used_line() {
+        let reads = [(A, false), (B, false), (A, false), (C, false), (A, false)];
+        assert_eq!(
+            run(cache(Replacement::Lru, WritePolicy::WriteBack), &reads),
+            (2, 3, 0)
+        );
+    }
+
+    #[test]
+    fn fifo_evicts_the_oldest_fill() {
+        let reads = [(A, false), (B, false), (A, false), (C, false), (A, false)];
+        assert_eq!(
+            run(cache(Replacement::Fifo, WritePolicy::WriteBack), &reads),
+            (1, 4, 0)
+        );
+    }
+
+    #[test]
+    fn write_back_writes_dirty_victims() {
+        let accesses = [(A, true), (B, true), (A, false), (C, false), (A, false)];
+        // LRU evicts B once; FIFO evicts A for C, then B for A
+        assert_eq!(
+            run(cache(Replacement::Lru, WritePolicy::WriteBack), &accesses),
+            (2, 3, 1)
+        );
+        assert_eq!(
+            run(cache(Replacement::Fifo, WritePolicy::WriteBack), &accesses),
+            (1, 4, 2)
+        );
+        // A clean victim costs nothing
+        let reads = [(A, false), (B, false), (C, false)];
+        assert_eq!(
+            run(cache(Replacement::Lru, WritePolicy::WriteBack), &reads),
+            (0, 3, 0)
+        );
+    }
+
+    #[test]
+    fn write_through_writes_every_store_without_allocating() {
+        let accesses = [(A, true), (A, false), (A, true), (B, false), (B, true)];
+        // The first store misses and leaves A uncached, so the load misses too
+        assert_eq!(
+            run(
+                cache(Replacement::Lru, WritePolicy::WriteThrough),
+                &accesses
+            ),
+            (2, 3, 3)
+        );
+    }
+
+    #[test]
+    fn addresses_split_into_tag_set_and_offset() {
+        let mut cache = cache(Replacement::Lru, WritePolicy::WriteBack);
+        cache.config.associativity = 1;
+        assert_eq!(
+            (
+                cache.config.offset_bits(),
+                cache.config.index_bits(),
+                cache.config.tag_bits()
+            ),
+            (4, 1, 27)
+        );
+        assert_eq!(cache.access(0x1001_0014, false), Some(false));
+        assert_eq!(cache.access(0x1001_001C, false), Some(true));
+        let (set, way) = cache.lookup(0x1001_0010).expect("block is cached");
+        assert_eq!(set, 1);
+        assert_eq!(
+            cache.block_address(set, &cache.sets()[set][way]),
+            0x1001_0010
+        );
+
+        cache.config.enabled = false;
+        assert_eq!(cache.access(A, false), None);
+    }
+
+    #[test]
+    fn loaded_geometry_is_rounded_to_powers_of_two() {
+        let cache: Cache =
+            ron::from_str("(config: (enabled: true, size: 300, block_size: 12, associativity: 3))")
+                .expect("cache deserializes");
+        let config = cache.config;
+        assert_eq!(
+            (config.size, config.block_size, config.associativity),
+            (256, 8, 2)
+        );
+        assert_eq!(
+            (config.sets(), config.offset_bits(), config.index_bits()),
+            (16, 3, 4)
+        );
+
+        let config = CacheConfig {
+            size: 0,
+            block_size: 1 << 20,
+            associativity: 0,
+            ..CacheConfig::default()
+        }
+        .normalized();
+        assert_eq!(
+            (config.size, config.block_size, config.associativity),
+            (4, 4, 1)
+        );
+        let config = CacheConfig {
+            size: u32::MAX,
+            associativity: 1000,
+            ..CacheConfig::default()
+        }
+        .normalized();
+        assert_eq!((config.size, config.associativity), (MAX_SIZE, 512));
+        assert_eq!(CacheConfig::default().normalized(), CacheConfig::default());
+    }
+}
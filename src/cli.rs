@@ -0,0 +1,403 @@
+//! Headless command-line runner: assemble or load, execute and print the final state
+
+use std::io::{BufRead as _, Write as _};
+
+use crate::assembler;
+use crate::cp1::FP_REGISTER_NAMES;
+use crate::cpu::{Cpu, Exception};
+use crate::disasm;
+use crate::elf;
+use crate::image::{self, ImageFormat};
+use crate::isa::REGISTER_NAMES;
+use crate::machine::{Machine, RuntimeError, Status};
+use crate::memory::Endianness;
+use crate::syscall::Console;
+
+const USAGE: &str = concat!(
+    "Usage: ",
+    env!("CARGO_PKG_NAME"),
+    " run <program.asm | program.elf> [options]
+
+Options:
+  --max-steps N         Stop after N instructions (default 1000000)
+  --dump-regs           Print all registers when execution stops
+  --dump-mem START..END Print the words in [START, END) when execution stops
+  --switches N          Set the DIP switches read from 0xFFFF0014 (default 0)
+  --export-mem START..END FILE
+                        Write the words in [START, END) to FILE when execution
+                        stops, in the format given by its extension: .hex
+                        (Intel HEX), .bin (raw, in the program's byte order),
+                        .txt (Logisim v2.0 raw) or .mem ($readmemh)
+  --export-format F     Use format F for every --export-mem instead: ihex,
+                        bin-be, bin-le, logisim or memh
+
+ELF32 MIPS executables of either byte order are loaded as they are; any
+other file is assembled.
+
+Syscall output goes to stdout and read services take lines from stdin. The
+memory-mapped terminal at 0xFFFF0000 shares them: bytes it transmits are
+written to stdout, and when the program polls or enables the receiver with
+nothing left to receive, the next line of stdin is typed into it.
+
+Exit status: the program's exit code after `exit`/`exit2`, 0 when it stops
+at `break`, 1 on an assembly or runtime error, 2 on bad arguments, 3 when
+the step limit is reached."
+);
+
+const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+/// Syscall console bound to the process's stdin and stdout
+struct StdioConsole;
+
+impl Console for StdioConsole {
+    fn print(&mut self, text: &str) {
+        let mut stdout = std::io::stdout().lock();
+        // Nothing useful can be done if stdout has gone away
+        stdout.write_all(text.as_bytes()).ok();
+        stdout.flush().ok();
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_owned()),
+        }
+    }
+}
+
+/// Options parsed from the command line
+struct Options {
+    program: String,
+    max_steps: u64,
+    dump_regs: bool,
+    dump_mem: Vec<(u32, u32)>,
+    export_mem: Vec<(u32, u32, String)>,
+    export_format: Option<ImageFormat>,
+    switches: u32,
+}
+
+/// Run the command line `args` (without the executable name) and return the exit status
+pub fn run(args: &[String]) -> i32 {
+    if args
+        .iter()
+        .any(|arg| matches!(arg.as_str(), "-h" | "--help"))
+    {
+        println!("{USAGE}");
+        return 0;
+    }
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    let contents = match std::fs::read(&options.program) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {e}", options.program);
+            return 1;
+        }
+    };
+    let program = if elf::is_elf(&contents) {
+        match elf::read_elf(&contents) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("{}: error: {e}", options.program);
+                return 1;
+            }
+        }
+    } else {
+        let source = String::from_utf8_lossy(&contents);
+        match assembler::assemble(&source, Endianness::default()) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!(
+                    "{}:{}:{}: error: {}",
+                    options.program, e.line, e.column, e.message
+                );
+                return 1;
+            }
+        }
+    };
+
+    let mut machine = Machine::new();
+    machine.load_program(&program);
+    machine.set_switches(options.switches);
+    let mut console = StdioConsole;
+    let mut end_of_input = false;
+
+    let mut steps = 0;
+    let status = loop {
+        if steps == options.max_steps {
+            eprintln!("stopped: step limit of {} reached", options.max_steps);
+            break 3;
+        }
+        if machine.devices.uart.wants_input() && !end_of_input {
+            match console.read_line() {
+                Some(line) => machine.devices.uart.type_text(&format!("{line}\n")),
+                None => end_of_input = true,
+            }
+        }
+        let pc = machine.cpu.pc;
+        let step = machine.step(&mut console);
+        let output = machine.devices.uart.take_output();
+        if !output.is_empty() {
+            console.print(&output);
+        }
+        match step {
+            // Trapped to the program's own handler, which carries on
+            Ok(Status::Running | Status::Exception(_)) => steps += 1,
+            Ok(Status::Exited(code)) => break code,
+            Ok(Status::WaitingForInput) => {
+                eprintln!("error: end of input at 0x{pc:08X} after {steps} step(s)");
+                break 1;
+            }
+            Err(RuntimeError::Exception(Exception::Break)) => {
+                eprintln!("stopped: break at 0x{pc:08X} after {steps} step(s)");
+                break 0;
+            }
+            Err(e) => {
+                eprintln!("error: {e} at 0x{pc:08X} after {steps} step(s)");
+                break 1;
+            }
+        }
+    };
+
+    if options.dump_regs {
+        dump_registers(&machine.cpu);
+    }
+    for &(start, end) in &options.dump_mem {
+        dump_memory(&machine, start, end);
+    }
+    for (start, end, file) in &options.export_mem {
+        let format = options
+            .export_format
+            .or_else(|| format_for_file(file, machine.memory.endianness()));
+        let Some(format) = format else {
+            eprintln!("error: cannot tell the image format of {file}; use --export-format");
+            return 1;
+        };
+        let words = end.saturating_sub(*start).div_ceil(4);
+        let bytes = image::export_image(&machine.memory, format, *start, words);
+        if let Err(e) = std::fs::write(file, bytes) {
+            eprintln!("error: cannot write {file}: {e}");
+            return 1;
+        }
+    }
+    status
+}
+
+/// Image format implied by a file's extension
+fn format_for_file(file: &str, endianness: Endianness) -> Option<ImageFormat> {
+    let (_, extension) = file.rsplit_once('.')?;
+    match extension.to_ascii_lowercase().as_str() {
+        "bin" => Some(match endianness {
+            Endianness::Big => ImageFormat::BinaryBig,
+            Endianness::Little => ImageFormat::BinaryLittle,
+        }),
+        extension => ImageFormat::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    match args.next().map(String::as_str) {
+        Some("run") => {}
+        Some(other) => return Err(format!("unknown command '{other}'")),
+        None => return Err("no command given".to_owned()),
+    }
+
+    let mut options = Options {
+        program: String::new(),
+        max_steps: DEFAULT_MAX_STEPS,
+        dump_regs: false,
+        dump_mem: Vec::new(),
+        export_mem: Vec::new(),
+        export_format: None,
+        switches: 0,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-steps" => {
+                let value = args.next().ok_or("--max-steps needs a value")?;
+                options.max_steps = parse_number(value)
+                    .ok_or_else(|| format!("invalid step count '{value}'"))?
+                    as u64;
+            }
+            "--dump-regs" => options.dump_regs = true,
+            "--dump-mem" => {
+                let value = args.next().ok_or("--dump-mem needs a range")?;
+                options.dump_mem.push(parse_range(value)?);
+            }
+            "--switches" => {
+                let value = args.next().ok_or("--switches needs a value")?;
+                options.switches =
+                    parse_number(value).ok_or_else(|| format!("invalid switches '{value}'"))?;
+            }
+            "--export-mem" => {
+                let range = args.next().ok_or("--export-mem needs a range and a file")?;
+                let (start, end) = parse_range(range)?;
+                let file = args.next().ok_or("--export-mem needs a file")?;
+                options.export_mem.push((start, end, file.clone()));
+            }
+            "--export-format" => {
+                let value = args.next().ok_or("--export-format needs a format")?;
+                let format = ImageFormat::from_key(value)
+                    .ok_or_else(|| format!("unknown image format '{value}'"))?;
+                options.export_format = Some(format);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{flag}'")),
+            program if options.program.is_empty() => options.program = program.to_owned(),
+            extra => return Err(format!("unexpected argument '{extra}'")),
+        }
+    }
+    if options.program.is_empty() {
+        return Err("no program file given".to_owned());
+    }
+    Ok(options)
+}
+
+/// Parse a decimal or `0x` hexadecimal number
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Parse `START..END` into a word-aligned, half-open address range
+fn parse_range(text: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid memory range '{text}', expected START..END");
+    let (start, end) = text.split_once("..").ok_or_else(invalid)?;
+    let start = parse_number(start).ok_or_else(invalid)?;
+    let end = parse_number(end).ok_or_else(invalid)?;
+    if end < start {
+        return Err(invalid());
+    }
+    Ok((start & !3, end))
+}
+
+fn dump_registers(cpu: &Cpu) {
+    for (row, names) in REGISTER_NAMES.chunks(4).enumerate() {
+        let line: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(col, name)| format!("{name:>5} = 0x{:08X}", cpu.regs[row * 4 + col]))
+            .collect();
+        println!("{}", line.join("  "));
+    }
+    println!(
+        "{:>5} = 0x{:08X}  {:>5} = 0x{:08X}  {:>5} = 0x{:08X}",
+        "pc", cpu.pc, "hi", cpu.hi, "lo", cpu.lo
+    );
+    for (row, names) in FP_REGISTER_NAMES.chunks(4).enumerate() {
+        let line: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(col, name)| format!("{name:>5} = 0x{:08X}", cpu.cp1.regs[row * 4 + col]))
+            .collect();
+        println!("{}", line.join("  "));
+    }
+    println!("{:>5} = 0x{:08X}", "fcsr", cpu.cp1.fcsr);
+}
+
+fn dump_memory(machine: &Machine, start: u32, end: u32) {
+    for address in (start..end).step_by(4) {
+        let word = machine.memory.read_word(address);
+        let text = disasm::disassemble(word, address).unwrap_or_else(|| "???".to_owned());
+        if let Some(names) = machine.symbols.get(&address) {
+            println!("{names}:");
+        }
+        match machine.pseudo_sources.get(&address) {
+            Some(source) => println!("0x{address:08X}: 0x{word:08X}  {text:<24}  <= {source}"),
+            None => println!("0x{address:08X}: 0x{word:08X}  {text}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn numbers_in_decimal_and_hex() {
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("0x1F"), Some(31));
+        assert_eq!(parse_number("0XffffFFFF"), Some(u32::MAX));
+        assert_eq!(parse_number("4294967296"), None);
+        assert_eq!(parse_number("0x100000000"), None);
+        assert_eq!(parse_number("-1"), None);
+        assert_eq!(parse_number("0x"), None);
+    }
+
+    #[test]
+    fn ranges_are_word_aligned_and_ordered() {
+        assert_eq!(
+            parse_range("0x10010002..0x10010010"),
+            Ok((0x1001_0000, 0x1001_0010))
+        );
+        assert_eq!(parse_range("16..16"), Ok((16, 16)));
+        for bad in ["16..8", "16", "16..", "x..8"] {
+            assert!(parse_range(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse_args(&args(
+            "run prog.s --max-steps 0x10 --dump-regs --dump-mem 0..8 \
+             --switches 5 --export-mem 4..12 out.txt --export-format memh",
+        ))
+        .expect("arguments parse");
+        assert_eq!(options.program, "prog.s");
+        assert_eq!(options.max_steps, 16);
+        assert!(options.dump_regs);
+        assert_eq!(options.dump_mem, [(0, 8)]);
+        assert_eq!(options.switches, 5);
+        assert_eq!(options.export_mem, [(4, 12, "out.txt".to_owned())]);
+        assert_eq!(options.export_format, Some(ImageFormat::ReadMemH));
+
+        let error = |line: &str| parse_args(&args(line)).err().unwrap_or_default();
+        assert_eq!(error("prog.s"), "unknown command 'prog.s'");
+        assert_eq!(error("run"), "no program file given");
+        assert_eq!(error("run a.s b.s"), "unexpected argument 'b.s'");
+        assert_eq!(error("run a.s --fast"), "unknown option '--fast'");
+        assert_eq!(error("run a.s --max-steps"), "--max-steps needs a value");
+        assert_eq!(
+            error("run a.s --export-format elf"),
+            "unknown image format 'elf'"
+        );
+    }
+
+    /// Exit status of running `source` with the extra `options`
+    fn status(name: &str, source: &str, options: &str) -> i32 {
+        let path = std::env::temp_dir().join(format!("cli-{}-{name}.s", std::process::id()));
+        std::fs::write(&path, source).expect("temporary file is writable");
+        let mut args = vec!["run".to_owned(), path.display().to_string()];
+        args.extend(self::args(options));
+        let status = run(&args);
+        std::fs::remove_file(&path).ok();
+        status
+    }
+
+    #[test]
+    fn exit_status() {
+        assert_eq!(status("exit", "li $v0, 10\nsyscall\n", ""), 0);
+        assert_eq!(status("exit2", "li $a0, 7\nli $v0, 17\nsyscall\n", ""), 7);
+        assert_eq!(status("break", "break\n", ""), 0);
+        assert_eq!(status("bad-asm", "bogus $t0\n", ""), 1);
+        assert_eq!(status("fault", "lw $t0, 1($zero)\n", ""), 1);
+        assert_eq!(status("args", "break\n", "--max-steps x"), 2);
+        assert_eq!(status("limit", "loop: j loop\n", "--max-steps 10"), 3);
+        assert_eq!(run(&args("run /nonexistent/program.s")), 1);
+    }
+}
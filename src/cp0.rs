@@ -0,0 +1,224 @@
+//! Coprocessor 0: exception state and the Count/Compare timer
+
+/// Where every exception and interrupt transfers control, as in MARS
+pub const EXCEPTION_VECTOR: u32 = 0x8000_0180;
+
+/// CP0 register numbers, as written in `mfc0`/`mtc0`
+pub mod reg {
+    pub const BAD_VADDR: usize = 8;
+    pub const COUNT: usize = 9;
+    pub const COMPARE: usize = 11;
+    pub const STATUS: usize = 12;
+    pub const CAUSE: usize = 13;
+    pub const EPC: usize = 14;
+}
+
+/// Status: interrupts enabled
+pub const STATUS_IE: u32 = 1 << 0;
+/// Status: exception level, set while a handler runs; masks interrupts
+pub const STATUS_EXL: u32 = 1 << 1;
+/// Status and Cause: interrupt mask and pending bits, one per line
+pub const INTERRUPT_MASK: u32 = 0xFF00;
+/// Cause: the exception code field, bits 6..2
+const EXC_CODE_MASK: u32 = 0x7C;
+/// Cause: the exception was raised in a branch delay slot
+pub const CAUSE_BD: u32 = 1 << 31;
+/// Interrupt line raised when Count reaches Compare
+pub const TIMER_INTERRUPT: u32 = 7;
+
+/// Status after reset: user mode, interrupts enabled, every line unmasked (as in MARS)
+const STATUS_RESET: u32 = 0x0000_FF11;
+
+/// Names of the exception codes this core raises, indexed by code
+pub fn exc_code_name(code: u32) -> &'static str {
+    match code {
+        0 => "Int (interrupt)",
+        4 => "AdEL (address error on load or fetch)",
+        5 => "AdES (address error on store)",
+        8 => "Sys (syscall)",
+        9 => "Bp (breakpoint)",
+        10 => "RI (reserved instruction)",
+        12 => "Ov (arithmetic overflow)",
+        13 => "Tr (trap)",
+        _ => "unknown",
+    }
+}
+
+/// The coprocessor 0 registers this core implements
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cp0 {
+    pub status: u32,
+    pub cause: u32,
+    pub epc: u32,
+    pub bad_vaddr: u32,
+    pub count: u32,
+    pub compare: u32,
+}
+
+impl Default for Cp0 {
+    fn default() -> Self {
+        Self {
+            status: STATUS_RESET,
+            cause: 0,
+            epc: 0,
+            bad_vaddr: 0,
+            count: 0,
+            compare: 0,
+        }
+    }
+}
+
+impl Cp0 {
+    /// Value `mfc0` reads from register `index`; unimplemented registers read as zero
+    pub fn read(&self, index: usize) -> u32 {
+        match index {
+            reg::BAD_VADDR => self.bad_vaddr,
+            reg::COUNT => self.count,
+            reg::COMPARE => self.compare,
+            reg::STATUS => self.status,
+            reg::CAUSE => self.cause,
+            reg::EPC => self.epc,
+            _ => 0,
+        }
+    }
+
+    /// Apply `mtc0` to register `index`
+    ///
+    /// BadVAddr is read-only, only the two software interrupt bits of Cause
+    /// are writable, and writing Compare acknowledges the timer interrupt.
+    pub fn write(&mut self, index: usize, value: u32) {
+        match index {
+            reg::COUNT => self.count = value,
+            reg::COMPARE => {
+                self.compare = value;
+                self.set_interrupt(TIMER_INTERRUPT, false);
+            }
+            reg::STATUS => self.status = value,
+            reg::CAUSE => self.cause = (self.cause & !0x300) | (value & 0x300),
+            reg::EPC => self.epc = value,
+            _ => {}
+        }
+    }
+
+    /// Advance Count by one instruction, raising the timer interrupt when it reaches Compare
+    pub fn tick(&mut self) {
+        self.count = self.count.wrapping_add(1);
+        if self.count == self.compare {
+            self.set_interrupt(TIMER_INTERRUPT, true);
+        }
+    }
+
+    /// Set or clear the pending bit of interrupt `line` (0-7) in Cause
+    pub fn set_interrupt(&mut self, line: u32, pending: bool) {
+        let bit = 1 << (8 + line);
+        if pending {
+            self.cause |= bit;
+        } else {
+            self.cause &= !bit;
+        }
+    }
+
+    /// Whether an unmasked interrupt is pending and may be taken now
+    pub fn interrupt_pending(&self) -> bool {
+        self.status & STATUS_IE != 0
+            && self.status & STATUS_EXL == 0
+            && self.status & self.cause & INTERRUPT_MASK != 0
+    }
+
+    /// Record an exception raised at `pc` and return the handler address
+    ///
+    /// An exception in a branch delay slot sets BD and returns to the branch.
+    pub fn enter_exception(
+        &mut self,
+        code: u32,
+        pc: u32,
+        bad_address: Option<u32>,
+        in_delay_slot: bool,
+    ) -> u32 {
+        self.cause = (self.cause & !(EXC_CODE_MASK | CAUSE_BD)) | (code << 2);
+        self.epc = pc;
+        if in_delay_slot {
+            self.cause |= CAUSE_BD;
+            self.epc = pc.wrapping_sub(4);
+        }
+        if let Some(address) = bad_address {
+            self.bad_vaddr = address;
+        }
+        self.status |= STATUS_EXL;
+        EXCEPTION_VECTOR
+    }
+
+    /// Leave the handler (`eret`): clear EXL and return to EPC
+    pub fn return_from_exception(&mut self) -> u32 {
+        self.status &= !STATUS_EXL;
+        self.epc
+    }
+
+    /// Exception code of the most recent exception
+    pub fn exception_code(&self) -> u32 {
+        (self.cause & EXC_CODE_MASK) >> 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exception_in_delay_slot_points_epc_at_the_branch() {
+        let mut cp0 = Cp0::default();
+        let handler = cp0.enter_exception(4, 0x0040_0008, Some(0x1001_0001), true);
+        assert_eq!(handler, EXCEPTION_VECTOR);
+        assert_eq!(cp0.epc, 0x0040_0004);
+        assert_eq!(cp0.cause & CAUSE_BD, CAUSE_BD);
+        assert_eq!(cp0.exception_code(), 4);
+        assert_eq!(cp0.bad_vaddr, 0x1001_0001);
+        assert_eq!(cp0.status & STATUS_EXL, STATUS_EXL);
+        // Outside a slot EPC is the faulting instruction and BD clears
+        cp0.enter_exception(12, 0x0040_0010, None, false);
+        assert_eq!((cp0.epc, cp0.cause & CAUSE_BD), (0x0040_0010, 0));
+        assert_eq!(cp0.exception_code(), 12);
+        assert_eq!(cp0.bad_vaddr, 0x1001_0001);
+    }
+
+    #[test]
+    fn eret_clears_exception_level() {
+        let mut cp0 = Cp0::default();
+        cp0.enter_exception(8, 0x0040_0000, None, false);
+        cp0.write(reg::EPC, 0x0040_0004);
+        assert_eq!(cp0.return_from_exception(), 0x0040_0004);
+        assert_eq!(cp0.status & STATUS_EXL, 0);
+        assert_eq!(cp0.status, STATUS_RESET);
+    }
+
+    #[test]
+    fn only_software_interrupt_bits_of_cause_are_writable() {
+        let mut cp0 = Cp0::default();
+        cp0.enter_exception(10, 0, None, true);
+        cp0.set_interrupt(TIMER_INTERRUPT, true);
+        cp0.write(reg::CAUSE, 0xFFFF_FFFF);
+        assert_eq!(cp0.cause, CAUSE_BD | 1 << 15 | 0x300 | 10 << 2);
+        cp0.write(reg::CAUSE, 0);
+        assert_eq!(cp0.cause, CAUSE_BD | 1 << 15 | 10 << 2);
+        cp0.write(reg::BAD_VADDR, 0x1234);
+        assert_eq!(cp0.read(reg::BAD_VADDR), 0);
+    }
+
+    #[test]
+    fn timer_interrupts_when_count_reaches_compare() {
+        let mut cp0 = Cp0::default();
+        cp0.write(reg::COMPARE, 3);
+        cp0.tick();
+        cp0.tick();
+        assert!(!cp0.interrupt_pending());
+        cp0.tick();
+        assert!(cp0.interrupt_pending());
+        // Masked while a handler runs, acknowledged by writing Compare
+        cp0.status |= STATUS_EXL;
+        assert!(!cp0.interrupt_pending());
+        cp0.status &= !STATUS_EXL;
+        cp0.write(reg::COMPARE, 10);
+        assert!(!cp0.interrupt_pending());
+        assert_eq!(cp0.read(reg::COUNT), 3);
+    }
+}
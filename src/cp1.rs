@@ -0,0 +1,199 @@
+//! Coprocessor 1: the floating-point register file and FCSR
+
+/// FCSR register number for `cfc1`/`ctc1`
+pub const FCSR: usize = 31;
+
+/// FCSR: rounding mode, bits 1..0
+const ROUNDING_MASK: u32 = 0x3;
+
+/// FCSR bit holding condition flag `cc`; flag 0 sits apart from 1-7
+fn condition_bit(cc: usize) -> u32 {
+    match cc {
+        0 => 1 << 23,
+        _ => 1 << (24 + cc),
+    }
+}
+
+/// Conventional names of the 32 floating-point registers
+pub const FP_REGISTER_NAMES: [&str; 32] = [
+    "$f0", "$f1", "$f2", "$f3", "$f4", "$f5", "$f6", "$f7", "$f8", "$f9", "$f10", "$f11", "$f12",
+    "$f13", "$f14", "$f15", "$f16", "$f17", "$f18", "$f19", "$f20", "$f21", "$f22", "$f23", "$f24",
+    "$f25", "$f26", "$f27", "$f28", "$f29", "$f30", "$f31",
+];
+
+/// Parse a floating-point register operand: `$f0` to `$f31`
+pub fn parse_fp_register(name: &str) -> Option<usize> {
+    let index = name.strip_prefix("$f")?.parse::<usize>().ok()?;
+    (index < 32).then_some(index)
+}
+
+/// How results that don't fit exactly are rounded, from FCSR bits 1..0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Nearest,
+    TowardZero,
+    Up,
+    Down,
+}
+
+impl Rounding {
+    /// Round `value` to a word as `cvt.w`, `round.w`, `trunc.w`, `ceil.w`
+    /// and `floor.w` do; NaN and out-of-range values give 2^31 - 1
+    pub fn to_word(self, value: f64) -> u32 {
+        let rounded = match self {
+            Self::Nearest => value.round_ties_even(),
+            Self::TowardZero => value.trunc(),
+            Self::Up => value.ceil(),
+            Self::Down => value.floor(),
+        };
+        if rounded.is_nan() || rounded < i32::MIN as f64 || rounded > i32::MAX as f64 {
+            i32::MAX as u32
+        } else {
+            rounded as i32 as u32
+        }
+    }
+}
+
+/// The FPU: 32 single-precision registers, paired for doubles, and FCSR
+///
+/// A double occupies an even register and the one after it, with the low
+/// word in the even register, as in MARS.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cp1 {
+    pub regs: [u32; 32],
+    pub fcsr: u32,
+}
+
+impl Cp1 {
+    pub fn single(&self, index: usize) -> f32 {
+        f32::from_bits(self.regs[index])
+    }
+
+    pub fn set_single(&mut self, index: usize, value: f32) {
+        self.regs[index] = value.to_bits();
+    }
+
+    /// The double held in the pair starting at even register `index`
+    pub fn double(&self, index: usize) -> f64 {
+        f64::from_bits(self.double_bits(index))
+    }
+
+    /// Raw bits of the pair holding `index`; an odd index names the same
+    /// pair as the even register below it
+    pub fn double_bits(&self, index: usize) -> u64 {
+        let index = index & !1;
+        ((self.regs[index + 1] as u64) << 32) | self.regs[index] as u64
+    }
+
+    pub fn set_double(&mut self, index: usize, value: f64) {
+        let index = index & !1;
+        let bits = value.to_bits();
+        self.regs[index] = bits as u32;
+        self.regs[index + 1] = (bits >> 32) as u32;
+    }
+
+    /// Value `cfc1` reads; only FCSR is implemented
+    pub fn read_control(&self, index: usize) -> u32 {
+        if index == FCSR {
+            self.fcsr
+        } else {
+            0
+        }
+    }
+
+    pub fn write_control(&mut self, index: usize, value: u32) {
+        if index == FCSR {
+            self.fcsr = value;
+        }
+    }
+
+    /// Condition flag `cc` (0-7), set by `c.cond.fmt` and tested by `bc1t`/`bc1f`
+    pub fn condition(&self, cc: usize) -> bool {
+        self.fcsr & condition_bit(cc) != 0
+    }
+
+    pub fn set_condition(&mut self, cc: usize, value: bool) {
+        if value {
+            self.fcsr |= condition_bit(cc);
+        } else {
+            self.fcsr &= !condition_bit(cc);
+        }
+    }
+
+    /// The FCSR rounding mode used by `cvt.w`
+    pub fn rounding(&self) -> Rounding {
+        match self.fcsr & ROUNDING_MASK {
+            0 => Rounding::Nearest,
+            1 => Rounding::TowardZero,
+            2 => Rounding::Up,
+            _ => Rounding::Down,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounding_to_a_word() {
+        // (value, nearest, toward zero, up, down)
+        let table = [
+            (2.5, 2, 2, 3, 2),
+            (3.5, 4, 3, 4, 3),
+            (-2.5, -2, -2, -2, -3),
+            (-3.5, -4, -3, -3, -4),
+            (-0.5, 0, 0, 0, -1),
+        ];
+        for (value, nearest, toward_zero, up, down) in table {
+            let results = [
+                Rounding::Nearest,
+                Rounding::TowardZero,
+                Rounding::Up,
+                Rounding::Down,
+            ]
+            .map(|rounding| rounding.to_word(value) as i32);
+            assert_eq!(results, [nearest, toward_zero, up, down], "{value}");
+        }
+    }
+
+    #[test]
+    fn unrepresentable_words_saturate_to_max() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 3e9, -3e9] {
+            assert_eq!(Rounding::Nearest.to_word(value), i32::MAX as u32, "{value}");
+        }
+        assert_eq!(Rounding::Down.to_word(-2147483648.0), i32::MIN as u32);
+        assert_eq!(Rounding::Up.to_word(2147483646.5), i32::MAX as u32);
+        assert_eq!(Rounding::Up.to_word(2147483647.5), i32::MAX as u32);
+    }
+
+    #[test]
+    fn condition_flags_skip_fcsr_bit_24() {
+        let mut cp1 = Cp1::default();
+        cp1.set_condition(0, true);
+        assert_eq!(cp1.fcsr, 1 << 23);
+        for cc in 1..8 {
+            cp1.fcsr = 0;
+            cp1.set_condition(cc, true);
+            assert_eq!(cp1.fcsr, 1 << (24 + cc), "cc {cc}");
+            assert!(cp1.condition(cc) && !cp1.condition(0));
+            cp1.set_condition(cc, false);
+            assert_eq!(cp1.fcsr, 0);
+        }
+    }
+
+    #[test]
+    fn rounding_mode_comes_from_fcsr() {
+        let mut cp1 = Cp1::default();
+        let modes = [
+            Rounding::Nearest,
+            Rounding::TowardZero,
+            Rounding::Up,
+            Rounding::Down,
+        ];
+        for (bits, mode) in modes.into_iter().enumerate() {
+            cp1.write_control(FCSR, 0xF000_0000 | bits as u32);
+            assert_eq!(cp1.rounding(), mode);
+        }
+    }
+}
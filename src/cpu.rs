@@ -0,0 +1,819 @@
+use crate::cp0::Cp0;
+use crate::cp1::{Cp1, Rounding};
+use crate::isa::{self, cop0, cop1, funct, funct2, op, regimm, Operands};
+use crate::memory::{AddressError, Endianness, Memory};
+
+/// Reasons the CPU stopped before completing an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exception {
+    /// An enabled interrupt was pending before the instruction at PC
+    Interrupt,
+    /// Misaligned load or instruction fetch
+    AddressErrorLoad(u32),
+    /// Misaligned store
+    AddressErrorStore(u32),
+    /// The fetched word is not a MIPS32 instruction this core implements
+    ReservedInstruction(u32),
+    /// Signed overflow in `add`, `addi` or `sub`
+    IntegerOverflow,
+    Syscall,
+    Break,
+    /// A `teq` or `tne` condition held
+    Trap,
+}
+
+impl std::fmt::Display for Exception {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Interrupt => write!(f, "interrupt"),
+            Self::AddressErrorLoad(addr) => write!(f, "address error on load at 0x{addr:08X}"),
+            Self::AddressErrorStore(addr) => write!(f, "address error on store at 0x{addr:08X}"),
+            Self::ReservedInstruction(word) => write!(f, "reserved instruction 0x{word:08X}"),
+            Self::IntegerOverflow => write!(f, "arithmetic overflow"),
+            Self::Syscall => write!(f, "syscall"),
+            Self::Break => write!(f, "break"),
+            Self::Trap => write!(f, "trap"),
+        }
+    }
+}
+
+impl Exception {
+    /// The code recorded in the Cause register's ExcCode field
+    pub fn code(self) -> u32 {
+        match self {
+            Self::Interrupt => 0,
+            Self::AddressErrorLoad(_) => 4,
+            Self::AddressErrorStore(_) => 5,
+            Self::Syscall => 8,
+            Self::Break => 9,
+            Self::ReservedInstruction(_) => 10,
+            Self::IntegerOverflow => 12,
+            Self::Trap => 13,
+        }
+    }
+
+    /// The faulting address, recorded in BadVAddr
+    pub fn bad_address(self) -> Option<u32> {
+        match self {
+            Self::AddressErrorLoad(address) | Self::AddressErrorStore(address) => Some(address),
+            _ => None,
+        }
+    }
+}
+
+/// A register the register panel can show as written by the last instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    Gpr(usize),
+    Hi,
+    Lo,
+    Fpr(usize),
+}
+
+impl Register {
+    fn bit(self) -> u128 {
+        match self {
+            Self::Gpr(index) => 1 << index,
+            Self::Hi => 1 << 32,
+            Self::Lo => 1 << 33,
+            Self::Fpr(index) => 1 << (34 + index),
+        }
+    }
+}
+
+/// MIPS32 core: register file, PC, HI/LO, coprocessor 0 and the FPU
+///
+/// Branches and jumps take effect immediately (no delay slot), matching the
+/// default behaviour of MARS and SPIM, unless `delay_slots` is set.
+#[derive(Clone, Debug)]
+pub struct Cpu {
+    pub regs: [u32; 32],
+    pub pc: u32,
+    pub hi: u32,
+    pub lo: u32,
+    pub cp0: Cp0,
+    pub cp1: Cp1,
+    /// Branches and jumps take effect after the instruction that follows
+    /// them, and link past it, as on real MIPS hardware
+    pub delay_slots: bool,
+    /// Where to go after the instruction at PC, which is in a delay slot
+    delayed_branch: Option<u32>,
+    /// Registers written by the most recent instruction, one bit per `Register`
+    written: u128,
+}
+
+/// Initial `$gp`, the middle of the first 64 KiB of the data segment
+pub const GLOBAL_POINTER: u32 = 0x1000_8000;
+/// Initial `$sp`, just below the top of user space
+pub const STACK_POINTER: u32 = 0x7FFF_EFFC;
+
+impl Default for Cpu {
+    fn default() -> Self {
+        let mut regs = [0; 32];
+        regs[28] = GLOBAL_POINTER;
+        regs[29] = STACK_POINTER;
+        Self {
+            regs,
+            pc: 0,
+            hi: 0,
+            lo: 0,
+            cp0: Cp0::default(),
+            cp1: Cp1::default(),
+            delay_slots: false,
+            delayed_branch: None,
+            written: 0,
+        }
+    }
+}
+
+impl Cpu {
+    /// Return PC, HI, LO, CP0, the FPU and the registers to their power-on
+    /// values, keeping the delay slot setting
+    pub fn reset(&mut self) {
+        *self = Self {
+            delay_slots: self.delay_slots,
+            ..Self::default()
+        };
+    }
+
+    /// Whether the instruction at PC is in the delay slot of a branch
+    pub fn in_delay_slot(&self) -> bool {
+        self.delayed_branch.is_some()
+    }
+
+    /// Read a general purpose register
+    pub fn reg(&self, index: usize) -> u32 {
+        self.regs[index]
+    }
+
+    /// Write a general purpose register; writes to `$zero` are discarded
+    pub fn set_reg(&mut self, index: usize, value: u32) {
+        if index != 0 {
+            self.regs[index] = value;
+            self.written |= Register::Gpr(index).bit();
+        }
+    }
+
+    /// Whether the most recent instruction wrote `register`
+    pub fn was_written(&self, register: Register) -> bool {
+        self.written & register.bit() != 0
+    }
+
+    /// Write a floating-point register
+    pub fn set_fpr(&mut self, index: usize, value: u32) {
+        self.cp1.regs[index] = value;
+        self.written |= Register::Fpr(index).bit();
+    }
+
+    /// Write a single, or a double into the pair starting at `index`
+    fn set_float(&mut self, index: usize, value: f64, double: bool) {
+        if double {
+            self.cp1.set_double(index, value);
+            self.written |= Register::Fpr(index).bit() | Register::Fpr(index + 1).bit();
+        } else {
+            self.set_fpr(index, (value as f32).to_bits());
+        }
+    }
+
+    fn set_hi(&mut self, value: u32) {
+        self.hi = value;
+        self.written |= Register::Hi.bit();
+    }
+
+    fn set_lo(&mut self, value: u32) {
+        self.lo = value;
+        self.written |= Register::Lo.bit();
+    }
+
+    /// Fetch, decode and execute the instruction at PC
+    ///
+    /// On an exception PC is left pointing at the faulting instruction and no
+    /// architectural state has been changed.
+    pub fn step(&mut self, memory: &mut Memory) -> Result<(), Exception> {
+        self.written = 0;
+        let word = memory.load_word(self.pc).map_err(load_error)?;
+        let transfer = self.execute(word, memory)?;
+        let pc4 = self.pc.wrapping_add(4);
+        self.pc = match (self.delayed_branch.take(), transfer) {
+            (Some(target), _) => target,
+            // A taken branch runs its delay slot first, even when it targets
+            // the slot itself; a branch not taken just falls through into it
+            (None, Some(target)) if self.delay_slots && isa::is_branch(word) => {
+                self.delayed_branch = Some(target);
+                pc4
+            }
+            (None, Some(target)) => target,
+            (None, None) => pc4,
+        };
+        Ok(())
+    }
+
+    /// Move past the instruction at PC once the machine has completed it,
+    /// as after a syscall it serviced
+    pub fn advance(&mut self) {
+        self.pc = self
+            .delayed_branch
+            .take()
+            .unwrap_or(self.pc.wrapping_add(4));
+    }
+
+    /// Enter the exception handler for `exception` raised by the instruction at PC
+    pub fn take_exception(&mut self, exception: Exception) {
+        let in_delay_slot = self.delayed_branch.take().is_some();
+        self.pc = self.cp0.enter_exception(
+            exception.code(),
+            self.pc,
+            exception.bad_address(),
+            in_delay_slot,
+        );
+    }
+
+    /// Execute one instruction and return where a taken branch or jump goes,
+    /// or `None` to continue with the next instruction
+    fn execute(&mut self, word: u32, memory: &mut Memory) -> Result<Option<u32>, Exception> {
+        let pc4 = self.pc.wrapping_add(4);
+        // With delay slots, calls return past the instruction in the slot
+        let link = if self.delay_slots {
+            pc4.wrapping_add(4)
+        } else {
+            pc4
+        };
+        let rs = isa::rs(word);
+        let rt = isa::rt(word);
+        let rd = isa::rd(word);
+        let s = self.reg(rs);
+        let t = self.reg(rt);
+        let branch_target = pc4.wrapping_add(isa::simm(word) << 2);
+        let branch = |taken: bool| taken.then_some(branch_target);
+
+        match isa::opcode(word) {
+            op::SPECIAL => match isa::funct(word) {
+                funct::SLL => self.set_reg(rd, t << isa::shamt(word)),
+                funct::SRL => self.set_reg(rd, t >> isa::shamt(word)),
+                funct::SRA => self.set_reg(rd, ((t as i32) >> isa::shamt(word)) as u32),
+                funct::SLLV => self.set_reg(rd, t << (s & 0x1F)),
+                funct::SRLV => self.set_reg(rd, t >> (s & 0x1F)),
+                funct::SRAV => self.set_reg(rd, ((t as i32) >> (s & 0x1F)) as u32),
+                funct::JR => return Ok(Some(s)),
+                funct::JALR => {
+                    self.set_reg(rd, link);
+                    return Ok(Some(s));
+                }
+                funct::MOVZ => {
+                    if t == 0 {
+                        self.set_reg(rd, s);
+                    }
+                }
+                funct::MOVN => {
+                    if t != 0 {
+                        self.set_reg(rd, s);
+                    }
+                }
+                funct::SYSCALL => return Err(Exception::Syscall),
+                funct::BREAK => return Err(Exception::Break),
+                funct::MFHI => self.set_reg(rd, self.hi),
+                funct::MTHI => self.set_hi(s),
+                funct::MFLO => self.set_reg(rd, self.lo),
+                funct::MTLO => self.set_lo(s),
+                funct::MULT => {
+                    let product = (s as i32 as i64) * (t as i32 as i64);
+                    self.set_hi_lo(product as u64);
+                }
+                funct::MULTU => {
+                    let product = (s as u64) * (t as u64);
+                    self.set_hi_lo(product);
+                }
+                funct::DIV => {
+                    // Division by zero leaves HI/LO unpredictable; keep them unchanged
+                    if t != 0 {
+                        self.set_lo((s as i32).wrapping_div(t as i32) as u32);
+                        self.set_hi((s as i32).wrapping_rem(t as i32) as u32);
+                    }
+                }
+                funct::DIVU => {
+                    if t != 0 {
+                        self.set_lo(s / t);
+                        self.set_hi(s % t);
+                    }
+                }
+                funct::ADD => {
+                    let sum = (s as i32)
+                        .checked_add(t as i32)
+                        .ok_or(Exception::IntegerOverflow)?;
+                    self.set_reg(rd, sum as u32);
+                }
+                funct::ADDU => self.set_reg(rd, s.wrapping_add(t)),
+                funct::SUB => {
+                    let diff = (s as i32)
+                        .checked_sub(t as i32)
+                        .ok_or(Exception::IntegerOverflow)?;
+                    self.set_reg(rd, diff as u32);
+                }
+                funct::SUBU => self.set_reg(rd, s.wrapping_sub(t)),
+                funct::AND => self.set_reg(rd, s & t),
+                funct::OR => self.set_reg(rd, s | t),
+                funct::XOR => self.set_reg(rd, s ^ t),
+                funct::NOR => self.set_reg(rd, !(s | t)),
+                funct::SLT => self.set_reg(rd, ((s as i32) < (t as i32)) as u32),
+                funct::SLTU => self.set_reg(rd, (s < t) as u32),
+                funct::TEQ if s == t => return Err(Exception::Trap),
+                funct::TNE if s != t => return Err(Exception::Trap),
+                funct::TEQ | funct::TNE => {}
+                _ => return Err(Exception::ReservedInstruction(word)),
+            },
+            op::SPECIAL2 => match isa::funct(word) {
+                funct2::MADD => {
+                    let product = (s as i32 as i64) * (t as i32 as i64);
+                    self.set_hi_lo(self.hi_lo().wrapping_add(product as u64));
+                }
+                funct2::MADDU => {
+                    let product = (s as u64) * (t as u64);
+                    self.set_hi_lo(self.hi_lo().wrapping_add(product));
+                }
+                funct2::MUL => self.set_reg(rd, (s as i32).wrapping_mul(t as i32) as u32),
+                funct2::MSUB => {
+                    let product = (s as i32 as i64) * (t as i32 as i64);
+                    self.set_hi_lo(self.hi_lo().wrapping_sub(product as u64));
+                }
+                funct2::MSUBU => {
+                    let product = (s as u64) * (t as u64);
+                    self.set_hi_lo(self.hi_lo().wrapping_sub(product));
+                }
+                funct2::CLZ => self.set_reg(rd, s.leading_zeros()),
+                funct2::CLO => self.set_reg(rd, s.leading_ones()),
+                _ => return Err(Exception::ReservedInstruction(word)),
+            },
+            op::REGIMM => {
+                let taken = match rt {
+                    regimm::BLTZ | regimm::BLTZAL => (s as i32) < 0,
+                    regimm::BGEZ | regimm::BGEZAL => (s as i32) >= 0,
+                    _ => return Err(Exception::ReservedInstruction(word)),
+                };
+                if rt == regimm::BLTZAL || rt == regimm::BGEZAL {
+                    self.set_reg(31, link);
+                }
+                return Ok(branch(taken));
+            }
+            op::J => return Ok(Some((pc4 & 0xF000_0000) | (isa::target(word) << 2))),
+            op::JAL => {
+                self.set_reg(31, link);
+                return Ok(Some((pc4 & 0xF000_0000) | (isa::target(word) << 2)));
+            }
+            op::BEQ => return Ok(branch(s == t)),
+            op::BNE => return Ok(branch(s != t)),
+            op::BLEZ => return Ok(branch((s as i32) <= 0)),
+            op::BGTZ => return Ok(branch((s as i32) > 0)),
+            op::ADDI => {
+                let sum = (s as i32)
+                    .checked_add(isa::simm(word) as i32)
+                    .ok_or(Exception::IntegerOverflow)?;
+                self.set_reg(rt, sum as u32);
+            }
+            op::ADDIU => self.set_reg(rt, s.wrapping_add(isa::simm(word))),
+            op::SLTI => self.set_reg(rt, ((s as i32) < (isa::simm(word) as i32)) as u32),
+            op::SLTIU => self.set_reg(rt, (s < isa::simm(word)) as u32),
+            op::ANDI => self.set_reg(rt, s & isa::imm(word)),
+            op::ORI => self.set_reg(rt, s | isa::imm(word)),
+            op::XORI => self.set_reg(rt, s ^ isa::imm(word)),
+            op::LUI => self.set_reg(rt, isa::imm(word) << 16),
+            op::COP0 => match isa::rs(word) as u32 {
+                cop0::MF => self.set_reg(rt, self.cp0.read(rd)),
+                cop0::MT => self.cp0.write(rd, t),
+                cop0::CO if isa::funct(word) == cop0::ERET => {
+                    return Ok(Some(self.cp0.return_from_exception()));
+                }
+                _ => return Err(Exception::ReservedInstruction(word)),
+            },
+            op::COP1 => return self.execute_cop1(word, branch_target),
+            op::LWC1 => {
+                let addr = s.wrapping_add(isa::simm(word));
+                let value = memory.load_word(addr).map_err(load_error)?;
+                self.set_fpr(rt, value);
+            }
+            op::SWC1 => {
+                let addr = s.wrapping_add(isa::simm(word));
+                memory
+                    .store_word(addr, self.cp1.regs[rt])
+                    .map_err(store_error)?;
+            }
+            op::LDC1 | op::SDC1 if rt % 2 != 0 => {
+                return Err(Exception::ReservedInstruction(word));
+            }
+            op::LDC1 => {
+                let addr = s.wrapping_add(isa::simm(word));
+                if addr % 8 != 0 {
+                    return Err(Exception::AddressErrorLoad(addr));
+                }
+                let first = memory.load_word(addr).map_err(load_error)?;
+                let second = memory.load_word(addr.wrapping_add(4)).map_err(load_error)?;
+                let (low, high) = match memory.endianness() {
+                    Endianness::Big => (second, first),
+                    Endianness::Little => (first, second),
+                };
+                self.set_fpr(rt, low);
+                self.set_fpr(rt + 1, high);
+            }
+            op::SDC1 => {
+                let addr = s.wrapping_add(isa::simm(word));
+                if addr % 8 != 0 {
+                    return Err(Exception::AddressErrorStore(addr));
+                }
+                let (low, high) = (self.cp1.regs[rt], self.cp1.regs[rt + 1]);
+                let (first, second) = match memory.endianness() {
+                    Endianness::Big => (high, low),
+                    Endianness::Little => (low, high),
+                };
+                memory.store_word(addr, first).map_err(store_error)?;
+                memory
+                    .store_word(addr.wrapping_add(4), second)
+                    .map_err(store_error)?;
+            }
+            op::LB | op::LH | op::LW | op::LBU | op::LHU => {
+                let addr = s.wrapping_add(isa::simm(word));
+                let value = match isa::opcode(word) {
+                    op::LB => memory.load_byte(addr) as i8 as i32 as u32,
+                    op::LBU => memory.load_byte(addr) as u32,
+                    op::LH => memory.load_half(addr).map_err(load_error)? as i16 as i32 as u32,
+                    op::LHU => memory.load_half(addr).map_err(load_error)? as u32,
+                    _ => memory.load_word(addr).map_err(load_error)?,
+                };
+                self.set_reg(rt, value);
+            }
+            // Unaligned word access in two halves: lwl/swl handle the bytes
+            // from the address to the word boundary in the direction of the
+            // most significant byte, lwr/swr the rest. They merge whole
+            // words, so a device register reads as it does for `lw`; the
+            // byte-lane placement that byte and halfword loads get doesn't
+            // apply, and each half counts as a separate read of the register
+            op::LWL | op::LWR | op::SWL | op::SWR => {
+                let addr = s.wrapping_add(isa::simm(word));
+                let aligned = addr & !3;
+                let memory_word = memory.read_word(aligned);
+                // Bytes between the most significant end of the word and `addr`
+                let left = 24 - memory.endianness().byte_shift(addr);
+                let right = 24 - left;
+                match isa::opcode(word) {
+                    op::LWL => {
+                        self.set_reg(rt, (memory_word << left) | (t & ((1 << left) - 1)));
+                    }
+                    op::LWR => {
+                        let kept = !(u32::MAX >> right);
+                        self.set_reg(rt, (memory_word >> right) | (t & kept));
+                    }
+                    op::SWL => {
+                        let kept = !(u32::MAX >> left);
+                        let value = (memory_word & kept) | (t >> left);
+                        memory.store_word(aligned, value).map_err(store_error)?;
+                    }
+                    _ => {
+                        let value = (t << right) | (memory_word & ((1 << right) - 1));
+                        memory.store_word(aligned, value).map_err(store_error)?;
+                    }
+                }
+            }
+            op::SB | op::SH | op::SW => {
+                let addr = s.wrapping_add(isa::simm(word));
+                match isa::opcode(word) {
+                    op::SB => memory.store_byte(addr, t as u8),
+                    op::SH => memory.store_half(addr, t as u16).map_err(store_error)?,
+                    _ => memory.store_word(addr, t).map_err(store_error)?,
+                }
+            }
+            _ => return Err(Exception::ReservedInstruction(word)),
+        }
+        Ok(None)
+    }
+
+    /// Execute a COP1 instruction: moves, `bc1t`/`bc1f` and arithmetic
+    fn execute_cop1(&mut self, word: u32, branch_target: u32) -> Result<Option<u32>, Exception> {
+        let reserved = Exception::ReservedInstruction(word);
+        let spec = isa::decode(word).ok_or(reserved)?;
+        let rt = isa::rt(word);
+        let (ft, fs, fd) = (rt, isa::rd(word), isa::shamt(word) as usize);
+
+        // Doubles live in even/odd pairs; only the fields the form uses count
+        let (double_result, double_sources) = spec.fp_doubles();
+        let has_ft = matches!(spec.operands, Operands::FdFsFt | Operands::FsFt);
+        let has_fd = matches!(spec.operands, Operands::FdFsFt | Operands::FdFs);
+        let odd = |index: usize| index % 2 != 0;
+        if (double_result && has_fd && odd(fd))
+            || (double_sources && (odd(fs) || (has_ft && odd(ft))))
+        {
+            return Err(reserved);
+        }
+
+        let fmt = isa::rs(word) as u32;
+        let read = |index: usize| match fmt {
+            cop1::D => self.cp1.double(index),
+            cop1::W => self.cp1.regs[index] as i32 as f64,
+            _ => self.cp1.single(index) as f64,
+        };
+        let a = read(fs);
+        let b = if has_ft { read(ft) } else { 0.0 };
+
+        match (fmt, isa::funct(word)) {
+            (cop1::MF, _) => self.set_reg(rt, self.cp1.regs[fs]),
+            (cop1::MT, _) => self.set_fpr(fs, self.reg(rt)),
+            (cop1::CF, _) => self.set_reg(rt, self.cp1.read_control(fs)),
+            (cop1::CT, _) => self.cp1.write_control(fs, self.reg(rt)),
+            (cop1::BC, _) => {
+                let taken = self.cp1.condition(rt >> 2) == (rt & 1 != 0);
+                return Ok(taken.then_some(branch_target));
+            }
+            // Singles are computed in double precision and rounded once,
+            // which gives the correctly rounded single result
+            (_, cop1::ADD) => self.set_float(fd, a + b, double_result),
+            (_, cop1::SUB) => self.set_float(fd, a - b, double_result),
+            (_, cop1::MUL) => self.set_float(fd, a * b, double_result),
+            (_, cop1::DIV) => self.set_float(fd, a / b, double_result),
+            (_, cop1::SQRT) => self.set_float(fd, a.sqrt(), double_result),
+            (_, cop1::ABS) => self.set_float(fd, a.abs(), double_result),
+            (_, cop1::NEG) => self.set_float(fd, -a, double_result),
+            (_, cop1::MOV) => {
+                self.set_fpr(fd, self.cp1.regs[fs]);
+                if double_result {
+                    self.set_fpr(fd + 1, self.cp1.regs[fs + 1]);
+                }
+            }
+            (_, cop1::CVT_S | cop1::CVT_D) => self.set_float(fd, a, double_result),
+            (_, cop1::CVT_W) => self.set_fpr(fd, self.cp1.rounding().to_word(a)),
+            (_, cop1::ROUND_W) => self.set_fpr(fd, Rounding::Nearest.to_word(a)),
+            (_, cop1::TRUNC_W) => self.set_fpr(fd, Rounding::TowardZero.to_word(a)),
+            (_, cop1::CEIL_W) => self.set_fpr(fd, Rounding::Up.to_word(a)),
+            (_, cop1::FLOOR_W) => self.set_fpr(fd, Rounding::Down.to_word(a)),
+            (_, condition) => {
+                let holds = match condition {
+                    cop1::C_EQ => a == b,
+                    cop1::C_LT => a < b,
+                    _ => a <= b,
+                };
+                self.cp1.set_condition(fd >> 2, holds);
+            }
+        }
+        Ok(None)
+    }
+
+    fn hi_lo(&self) -> u64 {
+        ((self.hi as u64) << 32) | self.lo as u64
+    }
+
+    fn set_hi_lo(&mut self, value: u64) {
+        self.set_hi((value >> 32) as u32);
+        self.set_lo(value as u32);
+    }
+}
+
+fn load_error(e: AddressError) -> Exception {
+    Exception::AddressErrorLoad(e.0)
+}
+
+fn store_error(e: AddressError) -> Exception {
+    Exception::AddressErrorStore(e.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run the single instruction `word` at address 0
+    fn run(cpu: &mut Cpu, word: u32) -> Result<(), Exception> {
+        let mut memory = Memory::new();
+        memory.write_word(0, word);
+        cpu.pc = 0;
+        cpu.step(&mut memory)
+    }
+
+    #[test]
+    fn unary_double_ignores_ft() {
+        let mut cpu = Cpu::default();
+        cpu.cp1.set_double(2, 9.0);
+        // sqrt.d $f0, $f2 and neg.d $f0, $f2 with ft = 31
+        run(&mut cpu, 0x463F_1004).unwrap();
+        assert_eq!(cpu.cp1.double(0), 3.0);
+        run(&mut cpu, 0x463F_1007).unwrap();
+        assert_eq!(cpu.cp1.double(0), -9.0);
+    }
+
+    #[test]
+    fn float_to_word_conversions() {
+        let mut cpu = Cpu::default();
+        // round.w.d, trunc.w.d, ceil.w.d, floor.w.d and cvt.w.d $f0, $f2
+        let convert = |cpu: &mut Cpu, funct: u32, value: f64| {
+            cpu.cp1.set_double(2, value);
+            run(cpu, 0x4620_1000 | funct).unwrap();
+            cpu.cp1.regs[0] as i32
+        };
+        for (value, results) in [(2.5, [2, 2, 3, 2]), (-2.5, [-2, -2, -2, -3])] {
+            let converted = [0x0C, 0x0D, 0x0E, 0x0F].map(|funct| convert(&mut cpu, funct, value));
+            assert_eq!(converted, results, "{value}");
+        }
+        // cvt.w follows the FCSR rounding mode
+        assert_eq!(convert(&mut cpu, 0x24, 3.5), 4);
+        cpu.cp1.fcsr = 1;
+        assert_eq!(convert(&mut cpu, 0x24, 3.5), 3);
+        cpu.cp1.fcsr = 3;
+        assert_eq!(convert(&mut cpu, 0x24, -3.5), -4);
+        // cvt.w.s $f0, $f2
+        cpu.cp1.set_single(2, -7.5);
+        run(&mut cpu, 0x4600_1024).unwrap();
+        assert_eq!(cpu.cp1.regs[0] as i32, -8);
+    }
+
+    #[test]
+    fn odd_double_registers_are_reserved() {
+        let mut cpu = Cpu::default();
+        // add.d $f0, $f0, $f31
+        assert_eq!(
+            run(&mut cpu, 0x463F_0000),
+            Err(Exception::ReservedInstruction(0x463F_0000))
+        );
+        // sqrt.d $f1, $f0
+        assert_eq!(
+            run(&mut cpu, 0x4620_0044),
+            Err(Exception::ReservedInstruction(0x4620_0044))
+        );
+    }
+
+    #[test]
+    fn unaligned_word_little_endian() {
+        let mut memory = Memory::new();
+        memory.set_endianness(Endianness::Little);
+        memory.write_word(0x100, 0x1122_3344);
+        memory.write_word(0x104, 0x5566_7788);
+        // lwl $t0, 0x104($zero); lwr $t0, 0x101($zero); swl $t0, 0x10B($zero); swr $t0, 0x108($zero)
+        for (address, word) in [
+            (0, 0x8808_0104),
+            (4, 0x9808_0101),
+            (8, 0xA808_010B),
+            (12, 0xB808_0108),
+        ] {
+            memory.write_word(address, word);
+        }
+        let mut cpu = Cpu::default();
+        cpu.step(&mut memory).unwrap();
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.reg(8), 0x8811_2233);
+        cpu.step(&mut memory).unwrap();
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(memory.read_word(0x108), 0x8811_2233);
+    }
+
+    #[test]
+    fn exception_in_delay_slot_returns_to_branch() {
+        let mut memory = Memory::new();
+        // beq $zero, $zero, 0x14; break
+        memory.write_word(0, 0x1000_0004);
+        memory.write_word(4, 0x0000_000D);
+        let mut cpu = Cpu {
+            delay_slots: true,
+            ..Cpu::default()
+        };
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pc, 4);
+        assert!(cpu.in_delay_slot());
+        assert_eq!(cpu.step(&mut memory), Err(Exception::Break));
+        cpu.take_exception(Exception::Break);
+        assert_eq!(cpu.cp0.epc, 0);
+        assert_eq!(cpu.cp0.cause & crate::cp0::CAUSE_BD, crate::cp0::CAUSE_BD);
+        assert_eq!(cpu.cp0.exception_code(), 9);
+    }
+
+    #[test]
+    fn faults_leave_pc_and_registers_unchanged() {
+        let mut cpu = Cpu::default();
+        cpu.set_reg(8, i32::MAX as u32);
+        cpu.set_reg(9, 1);
+        cpu.set_reg(10, 0x1001_0002);
+        // add $t2, $t0, $t1
+        assert_eq!(run(&mut cpu, 0x0109_5020), Err(Exception::IntegerOverflow));
+        // addi $t2, $t0, 1
+        assert_eq!(run(&mut cpu, 0x210A_0001), Err(Exception::IntegerOverflow));
+        // lw $t2, 0($t2) and sw $t0, 1($t2)
+        assert_eq!(
+            run(&mut cpu, 0x8D4A_0000),
+            Err(Exception::AddressErrorLoad(0x1001_0002))
+        );
+        assert_eq!(
+            run(&mut cpu, 0xAD48_0001),
+            Err(Exception::AddressErrorStore(0x1001_0003))
+        );
+        assert_eq!(cpu.reg(10), 0x1001_0002);
+        assert_eq!(cpu.pc, 0);
+        // addu $t2, $t0, $t1 wraps instead
+        run(&mut cpu, 0x0109_5021).unwrap();
+        assert_eq!(cpu.reg(10), 0x8000_0000);
+        assert_eq!(cpu.pc, 4);
+    }
+
+    #[test]
+    fn alu_and_branches() {
+        let mut cpu = Cpu::default();
+        // addiu $t0, $zero, 5; sll $t1, $t0, 2; slt $t2, $t1, $t0
+        for word in [0x2408_0005, 0x0008_4880, 0x0128_502A] {
+            run(&mut cpu, word).unwrap();
+        }
+        assert_eq!((cpu.reg(8), cpu.reg(9), cpu.reg(10)), (5, 20, 0));
+        // bne $t2, $zero, 3 falls through; beq $t0, $t0, 3 is taken
+        run(&mut cpu, 0x1540_0003).unwrap();
+        assert_eq!(cpu.pc, 4);
+        run(&mut cpu, 0x1108_0003).unwrap();
+        assert_eq!(cpu.pc, 16);
+        // jal 0x00400010 links the next instruction
+        run(&mut cpu, 0x0C10_0004).unwrap();
+        assert_eq!((cpu.pc, cpu.reg(31)), (0x0040_0010, 4));
+        // writes to $zero are discarded
+        run(&mut cpu, 0x2400_0001).unwrap();
+        assert_eq!(cpu.reg(0), 0);
+    }
+
+    #[test]
+    fn exception_codes() {
+        let mut cpu = Cpu::default();
+        assert_eq!(run(&mut cpu, 0x0000_000C), Err(Exception::Syscall));
+        assert_eq!(run(&mut cpu, 0x0000_000D), Err(Exception::Break));
+        assert_eq!(
+            run(&mut cpu, 0xFC00_0000),
+            Err(Exception::ReservedInstruction(0xFC00_0000))
+        );
+        let codes = [
+            (Exception::Interrupt, 0),
+            (Exception::AddressErrorLoad(0), 4),
+            (Exception::AddressErrorStore(0), 5),
+            (Exception::Syscall, 8),
+            (Exception::Break, 9),
+            (Exception::ReservedInstruction(0), 10),
+            (Exception::IntegerOverflow, 12),
+            (Exception::Trap, 13),
+        ];
+        for (exception, code) in codes {
+            assert_eq!(exception.code(), code, "{exception}");
+            assert_ne!(crate::cp0::exc_code_name(code), "unknown");
+        }
+    }
+
+    #[test]
+    fn branch_to_its_own_delay_slot_is_taken() {
+        let mut memory = Memory::new();
+        // beq $zero, $zero, 0; addiu $t0, $t0, 1
+        memory.write_word(0, 0x1000_0000);
+        memory.write_word(4, 0x2508_0001);
+        let mut cpu = Cpu {
+            delay_slots: true,
+            ..Cpu::default()
+        };
+        for _ in 0..3 {
+            cpu.step(&mut memory).unwrap();
+        }
+        // The slot runs once as the delay slot and again as the target
+        assert_eq!((cpu.reg(8), cpu.pc), (2, 8));
+        // bne $zero, $zero, 0 is not taken, so the slot runs once
+        memory.write_word(0, 0x1400_0000);
+        let mut cpu = Cpu {
+            delay_slots: true,
+            ..Cpu::default()
+        };
+        for _ in 0..3 {
+            cpu.step(&mut memory).unwrap();
+        }
+        assert_eq!((cpu.reg(8), cpu.pc), (1, 12));
+    }
+
+    #[test]
+    fn traps_on_condition() {
+        let mut cpu = Cpu::default();
+        // teq $zero, $zero and tne $zero, $zero
+        assert_eq!(run(&mut cpu, 0x0000_0034), Err(Exception::Trap));
+        run(&mut cpu, 0x0000_0036).unwrap();
+        assert_eq!(cpu.pc, 4);
+    }
+
+    #[test]
+    fn only_the_last_instructions_writes_are_flagged() {
+        let mut cpu = Cpu::default();
+        let written = |cpu: &Cpu| -> Vec<Register> {
+            let registers = (0..32)
+                .map(Register::Gpr)
+                .chain([Register::Hi, Register::Lo])
+                .chain((0..32).map(Register::Fpr));
+            registers.filter(|&r| cpu.was_written(r)).collect()
+        };
+        // addiu $t0, $zero, 5
+        run(&mut cpu, 0x2408_0005).unwrap();
+        assert_eq!(written(&cpu), [Register::Gpr(8)]);
+        // mult $t0, $t0
+        run(&mut cpu, 0x0108_0018).unwrap();
+        assert_eq!(written(&cpu), [Register::Hi, Register::Lo]);
+        // sw $t0, 0x100($zero) writes memory but no register
+        run(&mut cpu, 0xAC08_0100).unwrap();
+        assert_eq!(written(&cpu), []);
+        // addiu $zero, $zero, 1 is discarded
+        run(&mut cpu, 0x2400_0001).unwrap();
+        assert_eq!(written(&cpu), []);
+        // sqrt.d $f0, $f2 writes both halves of the pair
+        run(&mut cpu, 0x4620_1004).unwrap();
+        assert_eq!(written(&cpu), [Register::Fpr(0), Register::Fpr(1)]);
+        // A fault writes nothing: lw $t1, 1($zero)
+        assert!(run(&mut cpu, 0x8C09_0001).is_err());
+        assert_eq!(written(&cpu), []);
+    }
+}
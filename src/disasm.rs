@@ -0,0 +1,96 @@
+//! Disassembler turning machine words back into MIPS assembly text
+
+use crate::cp1::FP_REGISTER_NAMES;
+use crate::isa::{self, Operands, REGISTER_NAMES};
+
+/// Disassemble `word` as if it were stored at `address`
+///
+/// Returns `None` when the word is not an instruction the core can execute.
+pub fn disassemble(word: u32, address: u32) -> Option<String> {
+    if word == 0 {
+        return Some("nop".to_owned());
+    }
+
+    let spec = isa::decode(word)?;
+    let m = spec.mnemonic;
+    let rs = REGISTER_NAMES[isa::rs(word)];
+    let rt = REGISTER_NAMES[isa::rt(word)];
+    let rd = REGISTER_NAMES[isa::rd(word)];
+    let ft = FP_REGISTER_NAMES[isa::rt(word)];
+    let fs = FP_REGISTER_NAMES[isa::rd(word)];
+    let fd = FP_REGISTER_NAMES[isa::shamt(word) as usize];
+    let simm = isa::simm(word) as i32;
+    let branch_target = address.wrapping_add(4).wrapping_add(isa::simm(word) << 2);
+
+    let text = match spec.operands {
+        Operands::RdRsRt => format!("{m} {rd}, {rs}, {rt}"),
+        Operands::RdRtShamt => format!("{m} {rd}, {rt}, {}", isa::shamt(word)),
+        Operands::RdRtRs => format!("{m} {rd}, {rt}, {rs}"),
+        Operands::RsRt => format!("{m} {rs}, {rt}"),
+        Operands::RdRs => format!("{m} {rd}, {rs}"),
+        Operands::Rd => format!("{m} {rd}"),
+        Operands::Rs => format!("{m} {rs}"),
+        Operands::Jalr if isa::rd(word) == 31 => format!("{m} {rs}"),
+        Operands::Jalr => format!("{m} {rd}, {rs}"),
+        Operands::None => m.to_owned(),
+        Operands::RtRsSimm => format!("{m} {rt}, {rs}, {simm}"),
+        Operands::RtRsImm => format!("{m} {rt}, {rs}, 0x{:04X}", isa::imm(word)),
+        Operands::RtImm => format!("{m} {rt}, 0x{:04X}", isa::imm(word)),
+        Operands::Memory => format!("{m} {rt}, {simm}({rs})"),
+        Operands::Branch2 => format!("{m} {rs}, {rt}, 0x{branch_target:08X}"),
+        Operands::Branch1 => format!("{m} {rs}, 0x{branch_target:08X}"),
+        Operands::CopMove => format!("{m} {rt}, ${}", isa::rd(word)),
+        Operands::FdFsFt => format!("{m} {fd}, {fs}, {ft}"),
+        Operands::FdFs => format!("{m} {fd}, {fs}"),
+        // Condition flag 0 is implied, as MARS writes it
+        Operands::FsFt => match isa::shamt(word) >> 2 {
+            0 => format!("{m} {fs}, {ft}"),
+            cc => format!("{m} {cc}, {fs}, {ft}"),
+        },
+        Operands::RtFs => format!("{m} {rt}, {fs}"),
+        Operands::FpMemory => format!("{m} {ft}, {simm}({rs})"),
+        Operands::Bc1 => match isa::rt(word) >> 2 {
+            0 => format!("{m} 0x{branch_target:08X}"),
+            cc => format!("{m} {cc}, 0x{branch_target:08X}"),
+        },
+        Operands::Jump => {
+            let target = (address.wrapping_add(4) & 0xF000_0000) | (isa::target(word) << 2);
+            format!("{m} 0x{target:08X}")
+        }
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_operands_and_targets() {
+        let table = [
+            (0x0000_0000, "nop"),
+            (0x27A9_FFF8, "addiu $t1, $sp, -8"),
+            (0x8D4A_0000, "lw $t2, 0($t2)"),
+            (0x1000_FFFF, "beq $zero, $zero, 0x00400000"),
+            (0x0C10_0004, "jal 0x00400010"),
+            (0x0120_F809, "jalr $t1"),
+            (0x0120_4009, "jalr $t0, $t1"),
+            (0x3C01_1001, "lui $at, 0x1001"),
+        ];
+        for (word, text) in table {
+            assert_eq!(
+                disassemble(word, 0x0040_0000).as_deref(),
+                Some(text),
+                "0x{word:08X}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_words_have_no_text() {
+        // SPECIAL funct 0x01, REGIMM rt 0x05 and opcode 0x3F, which rows mark as invalid
+        for word in [0x0000_0001, 0x0405_0000, 0xFC00_0000, 0xFFFF_FFFF] {
+            assert_eq!(disassemble(word, 0), None, "0x{word:08X}");
+        }
+    }
+}
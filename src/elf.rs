@@ -0,0 +1,282 @@
+//! Loader for statically linked MIPS ELF32 executables, either byte order
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::assembler::{Program, Segment, SegmentKind};
+use crate::memory::Endianness;
+
+/// The four bytes every ELF file starts with
+pub const ELF_MAGIC: &[u8; 4] = b"\x7FELF";
+
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+const ET_EXEC: u16 = 2;
+const EM_MIPS: u16 = 8;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xFFF1;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+/// Largest segment the loader accepts, so a corrupt header can't exhaust memory
+const MAX_SEGMENT_SIZE: u32 = 64 << 20;
+
+/// Why an ELF file could not be loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    NotElf,
+    /// A header or table extends past the end of the file
+    Truncated,
+    /// A well-formed ELF file this loader can't run
+    Unsupported(String),
+}
+
+impl std::fmt::Display for ElfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotElf => write!(f, "not an ELF file"),
+            Self::Truncated => write!(f, "ELF file is truncated"),
+            Self::Unsupported(reason) => write!(f, "unsupported ELF file: {reason}"),
+        }
+    }
+}
+
+/// Whether `bytes` look like an ELF file
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(ELF_MAGIC)
+}
+
+/// Bounds-checked reads in the file's byte order
+///
+/// Offsets are 64-bit so that adding a field offset to a corrupt table
+/// address can't overflow.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    endianness: Endianness,
+}
+
+impl Reader<'_> {
+    fn slice(&self, offset: u64, len: u32) -> Result<&[u8], ElfError> {
+        let start = usize::try_from(offset).map_err(|_| ElfError::Truncated)?;
+        let end = start.checked_add(len as usize).ok_or(ElfError::Truncated)?;
+        self.bytes.get(start..end).ok_or(ElfError::Truncated)
+    }
+
+    fn u8(&self, offset: u64) -> Result<u8, ElfError> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16, ElfError> {
+        let bytes = self.slice(offset, 2)?.try_into().expect("two bytes");
+        Ok(match self.endianness {
+            Endianness::Big => u16::from_be_bytes(bytes),
+            Endianness::Little => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32, ElfError> {
+        let bytes = self.slice(offset, 4)?.try_into().expect("four bytes");
+        Ok(match self.endianness {
+            Endianness::Big => u32::from_be_bytes(bytes),
+            Endianness::Little => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// NUL-terminated string at `offset`
+    fn string(&self, offset: u64) -> Result<String, ElfError> {
+        let start = usize::try_from(offset).map_err(|_| ElfError::Truncated)?;
+        let tail = self.bytes.get(start..).ok_or(ElfError::Truncated)?;
+        let len = tail
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(ElfError::Truncated)?;
+        Ok(String::from_utf8_lossy(&tail[..len]).into_owned())
+    }
+}
+
+/// Parse an executable into a `Program`: one segment per `PT_LOAD` header
+/// (zero-filled up to its memory size), the entry point, the file's byte
+/// order and the `.symtab` symbols as labels, to be run with delay slots
+pub fn read_elf(bytes: &[u8]) -> Result<Program, ElfError> {
+    if !is_elf(bytes) {
+        return Err(ElfError::NotElf);
+    }
+    let unsupported = |reason: &str| ElfError::Unsupported(reason.to_owned());
+    let endianness = match bytes.get(5) {
+        Some(&ELFDATA2MSB) => Endianness::Big,
+        Some(&ELFDATA2LSB) => Endianness::Little,
+        _ => return Err(unsupported("unknown byte order")),
+    };
+    let elf = Reader { bytes, endianness };
+    if elf.u8(4)? != ELFCLASS32 {
+        return Err(unsupported("only 32-bit files are supported"));
+    }
+    if elf.u16(18)? != EM_MIPS {
+        return Err(unsupported("not a MIPS executable"));
+    }
+    if elf.u16(16)? != ET_EXEC {
+        return Err(unsupported("only statically linked executables can be run"));
+    }
+
+    let entry = elf.u32(24)?;
+    let phoff = elf.u32(28)? as u64;
+    let (phentsize, phnum) = (elf.u16(42)? as u64, elf.u16(44)? as u64);
+    let mut segments = Vec::new();
+    for index in 0..phnum {
+        let header = phoff + index * phentsize;
+        if elf.u32(header)? != PT_LOAD {
+            continue;
+        }
+        let (offset, address) = (elf.u32(header + 4)?, elf.u32(header + 8)?);
+        let (file_size, memory_size) = (elf.u32(header + 16)?, elf.u32(header + 20)?);
+        if memory_size > MAX_SEGMENT_SIZE || file_size > memory_size {
+            return Err(unsupported("segment size out of range"));
+        }
+        let mut data = elf.slice(offset as u64, file_size)?.to_vec();
+        data.resize(memory_size as usize, 0);
+        let kind = if elf.u32(header + 24)? & PF_X != 0 {
+            SegmentKind::Text
+        } else {
+            SegmentKind::Data
+        };
+        segments.push(Segment {
+            kind,
+            address,
+            bytes: data,
+        });
+    }
+    if segments.is_empty() {
+        return Err(unsupported("no loadable segments"));
+    }
+
+    Ok(Program {
+        segments,
+        entry,
+        endianness,
+        labels: symbols(&elf)?,
+        pseudo_sources: BTreeMap::new(),
+        // Compilers fill the slot after every branch and jump
+        delay_slots: true,
+    })
+}
+
+/// Named code and data symbols from every `SHT_SYMTAB` section
+fn symbols(elf: &Reader<'_>) -> Result<HashMap<String, u32>, ElfError> {
+    let shoff = elf.u32(32)? as u64;
+    let (shentsize, shnum) = (elf.u16(46)? as u64, elf.u16(48)? as u64);
+    let mut labels = HashMap::new();
+    for index in 0..shnum {
+        let section = shoff + index * shentsize;
+        if elf.u32(section + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let (offset, size) = (elf.u32(section + 16)? as u64, elf.u32(section + 20)? as u64);
+        let entsize = elf.u32(section + 36)?.max(16) as usize;
+        // The linked section holds the symbol names
+        let strings = shoff + elf.u32(section + 24)? as u64 * shentsize;
+        let strtab = elf.u32(strings + 16)? as u64;
+        for symbol in (offset..offset + size).step_by(entsize) {
+            let kind = elf.u8(symbol + 12)? & 0xF;
+            let section_index = elf.u16(symbol + 14)?;
+            if matches!(kind, STT_SECTION | STT_FILE)
+                || matches!(section_index, SHN_UNDEF | SHN_ABS)
+            {
+                continue;
+            }
+            let name = elf.string(strtab + elf.u32(symbol)? as u64)?;
+            if !name.is_empty() {
+                labels.insert(name, elf.u32(symbol + 4)?);
+            }
+        }
+    }
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{Machine, Status};
+    use crate::syscall::TestConsole;
+
+    /// Big-endian executable built from `tests/fixtures/delay_slots.s`
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/delay_slots.elf");
+
+    fn patched(offset: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut file = FIXTURE.to_vec();
+        file[offset..offset + bytes.len()].copy_from_slice(bytes);
+        file
+    }
+
+    fn unsupported(bytes: &[u8]) -> String {
+        match read_elf(bytes) {
+            Err(ElfError::Unsupported(reason)) => reason,
+            other => panic!(
+                "expected an unsupported file, got {:?}",
+                other.map(|p| p.entry)
+            ),
+        }
+    }
+
+    #[test]
+    fn runs_compiled_code_with_delay_slots() {
+        let program = read_elf(FIXTURE).expect("fixture loads");
+        assert_eq!(program.entry, 0x0040_0000);
+        assert_eq!(program.endianness, Endianness::Big);
+        assert_eq!(program.labels.get("main"), Some(&0x0040_0000));
+        assert!(program.delay_slots);
+
+        let mut machine = Machine::new();
+        machine.load_program(&program);
+        let mut output = TestConsole::default();
+        let mut status = Ok(Status::Running);
+        for _ in 0..1000 {
+            status = machine.step(&mut output);
+            if status != Ok(Status::Running) {
+                break;
+            }
+        }
+        assert_eq!(status, Ok(Status::Exited(0)));
+        assert_eq!(output.output, "8 30 12345678");
+    }
+
+    #[test]
+    fn rejects_files_it_cannot_run() {
+        assert_eq!(
+            read_elf(b"#!/bin/sh\n").map(|p| p.entry),
+            Err(ElfError::NotElf)
+        );
+        assert_eq!(
+            read_elf(&FIXTURE[..40]).map(|p| p.entry),
+            Err(ElfError::Truncated)
+        );
+        assert_eq!(
+            unsupported(&patched(4, &[2])),
+            "only 32-bit files are supported"
+        );
+        assert_eq!(unsupported(&patched(5, &[3])), "unknown byte order");
+        assert_eq!(
+            unsupported(&patched(16, &[0, 1])),
+            "only statically linked executables can be run"
+        );
+        assert_eq!(unsupported(&patched(18, &[0, 3])), "not a MIPS executable");
+        // No program headers
+        assert_eq!(unsupported(&patched(44, &[0, 0])), "no loadable segments");
+        // First program header: memory size past the limit, then smaller than the file size
+        assert_eq!(
+            unsupported(&patched(72, &[0x10, 0, 0, 0])),
+            "segment size out of range"
+        );
+        assert_eq!(
+            unsupported(&patched(72, &[0, 0, 0, 4])),
+            "segment size out of range"
+        );
+        // Segment data past the end of the file
+        assert_eq!(
+            read_elf(&patched(56, &[0, 1, 0, 0])).map(|p| p.entry),
+            Err(ElfError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,547 @@
+//! Memory image files for exchanging memory contents with other tools:
+//! Intel HEX, raw binary, Logisim-evolution "v2.0 raw" and Verilog `$readmemh`
+
+use crate::memory::{Endianness, Memory};
+
+/// Header line of a Logisim-evolution memory image
+const LOGISIM_HEADER: &str = "v2.0 raw";
+
+/// Data bytes per Intel HEX record when exporting
+const HEX_RECORD_BYTES: u32 = 16;
+
+/// Words per line in an exported Logisim image
+const LOGISIM_LINE_WORDS: usize = 8;
+
+/// Shortest run of equal words an exported Logisim image writes as `N*value`
+const LOGISIM_MIN_RUN: usize = 4;
+
+/// Most words a single import may write, so a corrupt `N*value` can't stall the app
+const MAX_IMPORT_WORDS: u64 = 16 << 20;
+
+/// A memory image file format
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum ImageFormat {
+    /// Byte records at absolute addresses, in memory's byte order
+    #[default]
+    IntelHex,
+    /// Consecutive words, most significant byte first
+    BinaryBig,
+    /// Consecutive words, least significant byte first
+    BinaryLittle,
+    /// Logisim-evolution RAM/ROM contents: hex words with `N*value` runs
+    LogisimRaw,
+    /// Hex words as read by Verilog's `$readmemh`, with `@address` jumps
+    ReadMemH,
+}
+
+impl ImageFormat {
+    pub const ALL: [Self; 5] = [
+        Self::IntelHex,
+        Self::BinaryBig,
+        Self::BinaryLittle,
+        Self::LogisimRaw,
+        Self::ReadMemH,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::IntelHex => "Intel HEX",
+            Self::BinaryBig => "Raw binary (big endian)",
+            Self::BinaryLittle => "Raw binary (little endian)",
+            Self::LogisimRaw => "Logisim v2.0 raw",
+            Self::ReadMemH => "Verilog $readmemh",
+        }
+    }
+
+    /// Short name used on the command line
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::IntelHex => "ihex",
+            Self::BinaryBig => "bin-be",
+            Self::BinaryLittle => "bin-le",
+            Self::LogisimRaw => "logisim",
+            Self::ReadMemH => "memh",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.key() == key)
+    }
+
+    /// Usual file extension, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::IntelHex => "hex",
+            Self::BinaryBig | Self::BinaryLittle => "bin",
+            Self::LogisimRaw => "txt",
+            Self::ReadMemH => "mem",
+        }
+    }
+
+    /// Whether the format is text, as opposed to raw bytes
+    pub fn is_text(self) -> bool {
+        !matches!(self, Self::BinaryBig | Self::BinaryLittle)
+    }
+
+    /// Whether addresses come from the file rather than the load address
+    pub fn is_absolute(self) -> bool {
+        self == Self::IntelHex
+    }
+
+    /// Guess the format of a file from its name and contents; raw binary
+    /// takes the byte order of memory
+    ///
+    /// Returns `None` for anything that doesn't look like a memory image,
+    /// such as assembly source.
+    pub fn detect(name: &str, bytes: &[u8], endianness: Endianness) -> Option<Self> {
+        let extension = name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase())
+            .unwrap_or_default();
+        if extension == "bin" {
+            return Some(match endianness {
+                Endianness::Big => Self::BinaryBig,
+                Endianness::Little => Self::BinaryLittle,
+            });
+        }
+        let text = std::str::from_utf8(bytes).ok()?.trim_start();
+        if text.starts_with(LOGISIM_HEADER) {
+            Some(Self::LogisimRaw)
+        } else if text.starts_with(':') {
+            Some(Self::IntelHex)
+        } else if matches!(extension.as_str(), "mem" | "memh" | "hex") {
+            Some(Self::ReadMemH)
+        } else {
+            None
+        }
+    }
+}
+
+/// A memory image that could not be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageError {
+    /// 1-based line number, or 0 for the file as a whole
+    pub line: usize,
+    pub message: String,
+}
+
+impl ImageError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+/// What an import wrote
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Imported {
+    /// Lowest address written, or the load address if nothing was
+    pub lowest: u32,
+    pub bytes: u64,
+}
+
+/// Write `words` words of memory starting at `start` (rounded down to a
+/// word) in `format`
+pub fn export_image(memory: &Memory, format: ImageFormat, start: u32, words: u32) -> Vec<u8> {
+    let start = start & !3;
+    let addresses = (0..words).map(|i| start.wrapping_add(i * 4));
+    match format {
+        ImageFormat::IntelHex => export_intel_hex(memory, start, words.saturating_mul(4)),
+        ImageFormat::BinaryBig => addresses
+            .flat_map(|address| memory.read_word(address).to_be_bytes())
+            .collect(),
+        ImageFormat::BinaryLittle => addresses
+            .flat_map(|address| memory.read_word(address).to_le_bytes())
+            .collect(),
+        ImageFormat::LogisimRaw => {
+            let values: Vec<u32> = addresses.map(|address| memory.read_word(address)).collect();
+            export_logisim(&values).into_bytes()
+        }
+        ImageFormat::ReadMemH => {
+            let mut text = format!("// {words} word(s) from 0x{start:08X}\n");
+            for address in addresses {
+                text.push_str(&format!("{:08x}\n", memory.read_word(address)));
+            }
+            text.into_bytes()
+        }
+    }
+}
+
+/// Data records of up to 16 bytes, with an extended linear address record
+/// whenever the upper half of the address changes
+fn export_intel_hex(memory: &Memory, start: u32, length: u32) -> Vec<u8> {
+    let mut text = String::new();
+    let mut upper = None;
+    let mut offset = 0;
+    while offset < length {
+        let address = start.wrapping_add(offset);
+        if upper != Some(address >> 16) {
+            upper = Some(address >> 16);
+            text.push_str(&hex_record(0, 4, &((address >> 16) as u16).to_be_bytes()));
+        }
+        // Records may not cross a 64 KiB boundary
+        let count = HEX_RECORD_BYTES
+            .min(length - offset)
+            .min(0x1_0000 - (address & 0xFFFF));
+        let data: Vec<u8> = (0..count)
+            .map(|i| memory.load_byte(address.wrapping_add(i)))
+            .collect();
+        text.push_str(&hex_record(address as u16, 0, &data));
+        offset += count;
+    }
+    text.push_str(&hex_record(0, 1, &[]));
+    text.into_bytes()
+}
+
+/// One `:LLAAAATT<data>CC` line
+fn hex_record(offset: u16, kind: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(offset.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+        .wrapping_neg();
+    bytes.push(checksum);
+    let mut line = String::from(":");
+    for byte in bytes {
+        line.push_str(&format!("{byte:02X}"));
+    }
+    line.push('\n');
+    line
+}
+
+fn export_logisim(values: &[u32]) -> String {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let run = values[i..].iter().take_while(|&&v| v == values[i]).count();
+        if run >= LOGISIM_MIN_RUN {
+            tokens.push(format!("{run}*{:x}", values[i]));
+            i += run;
+        } else {
+            tokens.push(format!("{:x}", values[i]));
+            i += 1;
+        }
+    }
+    let mut text = format!("{LOGISIM_HEADER}\n");
+    for line in tokens.chunks(LOGISIM_LINE_WORDS) {
+        text.push_str(&line.join(" "));
+        text.push('\n');
+    }
+    text
+}
+
+/// Write the image in `bytes` into memory
+///
+/// Word formats place their first word at `base` (rounded down to a word);
+/// Intel HEX records carry their own addresses and ignore it.
+pub fn import_image(
+    memory: &mut Memory,
+    format: ImageFormat,
+    base: u32,
+    bytes: &[u8],
+) -> Result<Imported, ImageError> {
+    let base = base & !3;
+    let mut writer = Writer {
+        memory,
+        lowest: None,
+        bytes: 0,
+    };
+    match format {
+        ImageFormat::IntelHex => import_intel_hex(&mut writer, text(bytes)?)?,
+        ImageFormat::BinaryBig | ImageFormat::BinaryLittle => {
+            for (i, chunk) in bytes.chunks(4).enumerate() {
+                let mut word = [0; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                let value = match format {
+                    ImageFormat::BinaryBig => u32::from_be_bytes(word),
+                    _ => u32::from_le_bytes(word),
+                };
+                writer.word(base.wrapping_add(i as u32 * 4), value);
+            }
+        }
+        ImageFormat::LogisimRaw => import_logisim(&mut writer, base, text(bytes)?)?,
+        ImageFormat::ReadMemH => import_readmemh(&mut writer, base, text(bytes)?)?,
+    }
+    Ok(Imported {
+        lowest: writer.lowest.unwrap_or(base),
+        bytes: writer.bytes,
+    })
+}
+
+fn text(bytes: &[u8]) -> Result<&str, ImageError> {
+    std::str::from_utf8(bytes).map_err(|_| ImageError::new(0, "file is not text"))
+}
+
+/// Stores into memory, keeping track of what was written
+struct Writer<'a> {
+    memory: &'a mut Memory,
+    lowest: Option<u32>,
+    bytes: u64,
+}
+
+impl Writer<'_> {
+    fn note(&mut self, address: u32, size: u64) {
+        self.lowest = Some(self.lowest.map_or(address, |lowest| lowest.min(address)));
+        self.bytes += size;
+    }
+
+    fn byte(&mut self, address: u32, value: u8) {
+        self.memory.store_byte(address, value);
+        self.note(address, 1);
+    }
+
+    fn word(&mut self, address: u32, value: u32) {
+        self.memory.write_word(address, value);
+        self.note(address, 4);
+    }
+}
+
+fn import_intel_hex(writer: &mut Writer<'_>, text: &str) -> Result<(), ImageError> {
+    let mut upper = 0u32;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: &str| ImageError::new(number, message);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("record does not start with ':'"))?;
+        if digits.len() % 2 != 0 || !digits.is_ascii() {
+            return Err(error("record is not a sequence of hex bytes"));
+        }
+        let record = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| error("record is not a sequence of hex bytes"))?;
+        if record.len() < 5 || record.len() != 5 + record[0] as usize {
+            return Err(error("record length does not match its byte count"));
+        }
+        if record.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+        let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+        let data = &record[4..record.len() - 1];
+        let value = || match data {
+            &[high, low] => Ok(u16::from_be_bytes([high, low]) as u32),
+            _ => Err(error("address record must have two data bytes")),
+        };
+        match record[3] {
+            0 => {
+                for (i, &byte) in data.iter().enumerate() {
+                    writer.byte(upper.wrapping_add(offset + i as u32), byte);
+                }
+            }
+            1 => return Ok(()),
+            // Extended segment address: paragraph number
+            2 => upper = value()? << 4,
+            // Extended linear address: upper 16 bits
+            4 => upper = value()? << 16,
+            // Start addresses don't affect memory
+            3 | 5 => {}
+            kind => return Err(error(&format!("unknown record type {kind:02X}"))),
+        }
+    }
+    Err(ImageError::new(0, "missing end-of-file record"))
+}
+
+fn import_logisim(writer: &mut Writer<'_>, base: u32, text: &str) -> Result<(), ImageError> {
+    let mut lines = text.lines().enumerate();
+    let header = lines.find(|(_, line)| !line.trim().is_empty());
+    if !header.is_some_and(|(_, line)| line.trim() == LOGISIM_HEADER) {
+        return Err(ImageError::new(
+            1,
+            format!("expected '{LOGISIM_HEADER}' header"),
+        ));
+    }
+    let mut index: u64 = 0;
+    for (line_index, line) in lines {
+        let number = line_index + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        for token in line.split_whitespace() {
+            let invalid = || ImageError::new(number, format!("invalid value '{token}'"));
+            let (count, value) = match token.split_once('*') {
+                Some((count, value)) => (count.parse::<u64>().map_err(|_| invalid())?, value),
+                None => (1, token),
+            };
+            let value = u32::from_str_radix(value, 16).map_err(|_| invalid())?;
+            if index + count > MAX_IMPORT_WORDS {
+                return Err(ImageError::new(number, "image is too large"));
+            }
+            for _ in 0..count {
+                writer.word(base.wrapping_add(index as u32 * 4), value);
+                index += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn import_readmemh(writer: &mut Writer<'_>, base: u32, text: &str) -> Result<(), ImageError> {
+    let mut index: u32 = 0;
+    let mut in_comment = false;
+    for (line_index, line) in text.lines().enumerate() {
+        let number = line_index + 1;
+        for token in strip_comments(line, &mut in_comment).split_whitespace() {
+            let invalid = || ImageError::new(number, format!("invalid value '{token}'"));
+            let digits: String = token.chars().filter(|&c| c != '_').collect();
+            // `@n` moves to word n of the image
+            if let Some(address) = digits.strip_prefix('@') {
+                index = u32::from_str_radix(address, 16).map_err(|_| invalid())?;
+                continue;
+            }
+            let value = u32::from_str_radix(&digits, 16).map_err(|_| invalid())?;
+            writer.word(base.wrapping_add(index.wrapping_mul(4)), value);
+            index = index.wrapping_add(1);
+        }
+    }
+    Ok(())
+}
+
+/// Remove `//` and `/* */` comments from a line; `in_comment` carries an
+/// unterminated block comment over to the next line
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut kept = String::new();
+    let mut rest = line;
+    loop {
+        if *in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    rest = &rest[end + 2..];
+                    *in_comment = false;
+                }
+                None => return kept,
+            }
+        }
+        let line_comment = rest.find("//");
+        match rest.find("/*") {
+            Some(start) if line_comment.map_or(true, |line| start < line) => {
+                kept.push_str(&rest[..start]);
+                kept.push(' ');
+                rest = &rest[start + 2..];
+                *in_comment = true;
+            }
+            _ => {
+                kept.push_str(&rest[..line_comment.unwrap_or(rest.len())]);
+                return kept;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Memory holding a few words around a 64 KiB boundary, with a run long
+    /// enough for Logisim to compress
+    fn sample(endianness: Endianness) -> Memory {
+        let mut memory = Memory::new();
+        memory.set_endianness(endianness);
+        let words = [0x1234_5678, 0, 0, 0, 0, 0xDEAD_BEEF, 0x8000_0001, 7];
+        for (i, word) in words.into_iter().enumerate() {
+            memory.write_word(0x1000_FFF0 + i as u32 * 4, word);
+        }
+        memory
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let original = sample(endianness);
+            for format in ImageFormat::ALL {
+                let bytes = export_image(&original, format, 0x1000_FFF0, 8);
+                let mut memory = Memory::new();
+                memory.set_endianness(endianness);
+                let imported = import_image(&mut memory, format, 0x1000_FFF0, &bytes)
+                    .unwrap_or_else(|e| panic!("{format:?}: {e}"));
+                assert_eq!(imported.lowest, 0x1000_FFF0, "{format:?}");
+                assert_eq!(imported.bytes, 32, "{format:?}");
+                for i in 0..8 {
+                    let address = 0x1000_FFF0 + i * 4;
+                    assert_eq!(
+                        memory.read_word(address),
+                        original.read_word(address),
+                        "{format:?} {endianness:?} at 0x{address:08X}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn detects_formats_from_name_and_contents() {
+        let detect =
+            |name: &str, text: &str| ImageFormat::detect(name, text.as_bytes(), Endianness::Little);
+        assert_eq!(detect("a.bin", ""), Some(ImageFormat::BinaryLittle));
+        assert_eq!(
+            detect("a.txt", "v2.0 raw\n1 2\n"),
+            Some(ImageFormat::LogisimRaw)
+        );
+        assert_eq!(
+            detect("a.hex", ":00000001FF\n"),
+            Some(ImageFormat::IntelHex)
+        );
+        assert_eq!(detect("a.hex", "0000_0001\n"), Some(ImageFormat::ReadMemH));
+        assert_eq!(detect("a.s", "main: nop\n"), None);
+    }
+
+    #[test]
+    fn rejects_broken_intel_hex() {
+        let import = |text: &str| {
+            import_image(
+                &mut Memory::new(),
+                ImageFormat::IntelHex,
+                0,
+                text.as_bytes(),
+            )
+        };
+        assert_eq!(
+            import(":0400000012345678E8\n:00000001FE\n"),
+            Err(ImageError::new(2, "checksum mismatch"))
+        );
+        assert_eq!(
+            import(":0400000012345678E8\n"),
+            Err(ImageError::new(0, "missing end-of-file record"))
+        );
+        assert!(import(":0400000012345678E8\n:00000001FF\n").is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_logisim_images() {
+        let import = |text: &str| {
+            import_image(
+                &mut Memory::new(),
+                ImageFormat::LogisimRaw,
+                0,
+                text.as_bytes(),
+            )
+        };
+        assert_eq!(import("1 2 3\n").map_err(|e| e.line), Err(1));
+        assert_eq!(
+            import("v2.0 raw\n20000000*1\n"),
+            Err(ImageError::new(2, "image is too large"))
+        );
+        assert_eq!(
+            import("v2.0 raw\n1 x\n"),
+            Err(ImageError::new(2, "invalid value 'x'"))
+        );
+    }
+}
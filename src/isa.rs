@@ -0,0 +1,729 @@
+//! MIPS32 instruction encoding: field extraction, opcodes and the instruction table
+
+/// Primary opcode, bits 31..26
+pub fn opcode(word: u32) -> u32 {
+    word >> 26
+}
+
+/// First source register, bits 25..21
+pub fn rs(word: u32) -> usize {
+    ((word >> 21) & 0x1F) as usize
+}
+
+/// Second source / I-type destination register, bits 20..16
+pub fn rt(word: u32) -> usize {
+    ((word >> 16) & 0x1F) as usize
+}
+
+/// R-type destination register, bits 15..11
+pub fn rd(word: u32) -> usize {
+    ((word >> 11) & 0x1F) as usize
+}
+
+/// Shift amount, bits 10..6
+pub fn shamt(word: u32) -> u32 {
+    (word >> 6) & 0x1F
+}
+
+/// R-type function code, bits 5..0
+pub fn funct(word: u32) -> u32 {
+    word & 0x3F
+}
+
+/// 16-bit immediate, zero-extended
+pub fn imm(word: u32) -> u32 {
+    word & 0xFFFF
+}
+
+/// 16-bit immediate, sign-extended
+pub fn simm(word: u32) -> u32 {
+    word as u16 as i16 as i32 as u32
+}
+
+/// 26-bit jump target, bits 25..0
+pub fn target(word: u32) -> u32 {
+    word & 0x03FF_FFFF
+}
+
+/// Primary opcodes (bits 31..26)
+pub mod op {
+    pub const SPECIAL: u32 = 0x00;
+    pub const REGIMM: u32 = 0x01;
+    pub const J: u32 = 0x02;
+    pub const JAL: u32 = 0x03;
+    pub const BEQ: u32 = 0x04;
+    pub const BNE: u32 = 0x05;
+    pub const BLEZ: u32 = 0x06;
+    pub const BGTZ: u32 = 0x07;
+    pub const ADDI: u32 = 0x08;
+    pub const ADDIU: u32 = 0x09;
+    pub const SLTI: u32 = 0x0A;
+    pub const SLTIU: u32 = 0x0B;
+    pub const ANDI: u32 = 0x0C;
+    pub const ORI: u32 = 0x0D;
+    pub const XORI: u32 = 0x0E;
+    pub const LUI: u32 = 0x0F;
+    pub const COP0: u32 = 0x10;
+    pub const COP1: u32 = 0x11;
+    pub const SPECIAL2: u32 = 0x1C;
+    pub const LB: u32 = 0x20;
+    pub const LH: u32 = 0x21;
+    pub const LWL: u32 = 0x22;
+    pub const LW: u32 = 0x23;
+    pub const LBU: u32 = 0x24;
+    pub const LHU: u32 = 0x25;
+    pub const LWR: u32 = 0x26;
+    pub const SB: u32 = 0x28;
+    pub const SH: u32 = 0x29;
+    pub const SWL: u32 = 0x2A;
+    pub const SW: u32 = 0x2B;
+    pub const SWR: u32 = 0x2E;
+    pub const LWC1: u32 = 0x31;
+    pub const LDC1: u32 = 0x35;
+    pub const SWC1: u32 = 0x39;
+    pub const SDC1: u32 = 0x3D;
+}
+
+/// Whether a load/store opcode writes memory
+pub fn is_store(opcode: u32) -> bool {
+    matches!(
+        opcode,
+        op::SB | op::SH | op::SW | op::SWL | op::SWR | op::SWC1 | op::SDC1
+    )
+}
+
+/// Whether a word is a branch or jump, the instructions that have a delay slot
+pub fn is_branch(word: u32) -> bool {
+    decode(word).is_some_and(|spec| match spec.operands {
+        Operands::Branch1 | Operands::Branch2 | Operands::Jump | Operands::Jalr | Operands::Bc1 => {
+            true
+        }
+        Operands::Rs => spec.opcode == op::SPECIAL && spec.funct == funct::JR,
+        _ => false,
+    })
+}
+
+/// Bytes a load/store opcode reads or writes
+pub fn access_size(opcode: u32) -> u32 {
+    match opcode {
+        op::LB | op::LBU | op::SB => 1,
+        op::LH | op::LHU | op::SH => 2,
+        op::LDC1 | op::SDC1 => 8,
+        _ => 4,
+    }
+}
+
+/// Function codes for `op::SPECIAL` (bits 5..0)
+pub mod funct {
+    pub const SLL: u32 = 0x00;
+    pub const SRL: u32 = 0x02;
+    pub const SRA: u32 = 0x03;
+    pub const SLLV: u32 = 0x04;
+    pub const SRLV: u32 = 0x06;
+    pub const SRAV: u32 = 0x07;
+    pub const JR: u32 = 0x08;
+    pub const JALR: u32 = 0x09;
+    pub const MOVZ: u32 = 0x0A;
+    pub const MOVN: u32 = 0x0B;
+    pub const SYSCALL: u32 = 0x0C;
+    pub const BREAK: u32 = 0x0D;
+    pub const MFHI: u32 = 0x10;
+    pub const MTHI: u32 = 0x11;
+    pub const MFLO: u32 = 0x12;
+    pub const MTLO: u32 = 0x13;
+    pub const MULT: u32 = 0x18;
+    pub const MULTU: u32 = 0x19;
+    pub const DIV: u32 = 0x1A;
+    pub const DIVU: u32 = 0x1B;
+    pub const ADD: u32 = 0x20;
+    pub const ADDU: u32 = 0x21;
+    pub const SUB: u32 = 0x22;
+    pub const SUBU: u32 = 0x23;
+    pub const AND: u32 = 0x24;
+    pub const OR: u32 = 0x25;
+    pub const XOR: u32 = 0x26;
+    pub const NOR: u32 = 0x27;
+    pub const SLT: u32 = 0x2A;
+    pub const SLTU: u32 = 0x2B;
+    pub const TEQ: u32 = 0x34;
+    pub const TNE: u32 = 0x36;
+}
+
+/// Function codes for `op::SPECIAL2` (bits 5..0)
+pub mod funct2 {
+    pub const MADD: u32 = 0x00;
+    pub const MADDU: u32 = 0x01;
+    pub const MUL: u32 = 0x02;
+    pub const MSUB: u32 = 0x04;
+    pub const MSUBU: u32 = 0x05;
+    pub const CLZ: u32 = 0x20;
+    pub const CLO: u32 = 0x21;
+}
+
+/// `rs` field selectors for `op::COP0`, and the `funct` of `eret`
+pub mod cop0 {
+    pub const MF: u32 = 0x00;
+    pub const MT: u32 = 0x04;
+    /// Set in `rs` for coprocessor operations such as `eret`
+    pub const CO: u32 = 0x10;
+    pub const ERET: u32 = 0x18;
+}
+
+/// `rs` field selectors (formats and moves) and function codes for `op::COP1`
+pub mod cop1 {
+    pub const MF: u32 = 0x00;
+    pub const CF: u32 = 0x02;
+    pub const MT: u32 = 0x04;
+    pub const CT: u32 = 0x06;
+    /// `bc1f`/`bc1t`; bit 16 selects the sense
+    pub const BC: u32 = 0x08;
+    /// Single precision
+    pub const S: u32 = 0x10;
+    /// Double precision
+    pub const D: u32 = 0x11;
+    /// 32-bit integer word
+    pub const W: u32 = 0x14;
+
+    pub const ADD: u32 = 0x00;
+    pub const SUB: u32 = 0x01;
+    pub const MUL: u32 = 0x02;
+    pub const DIV: u32 = 0x03;
+    pub const SQRT: u32 = 0x04;
+    pub const ABS: u32 = 0x05;
+    pub const MOV: u32 = 0x06;
+    pub const NEG: u32 = 0x07;
+    pub const ROUND_W: u32 = 0x0C;
+    pub const TRUNC_W: u32 = 0x0D;
+    pub const CEIL_W: u32 = 0x0E;
+    pub const FLOOR_W: u32 = 0x0F;
+    pub const CVT_S: u32 = 0x20;
+    pub const CVT_D: u32 = 0x21;
+    pub const CVT_W: u32 = 0x24;
+    pub const C_EQ: u32 = 0x32;
+    pub const C_LT: u32 = 0x3C;
+    pub const C_LE: u32 = 0x3E;
+}
+
+/// `rt` field selectors for `op::REGIMM`
+pub mod regimm {
+    pub const BLTZ: usize = 0x00;
+    pub const BGEZ: usize = 0x01;
+    pub const BLTZAL: usize = 0x10;
+    pub const BGEZAL: usize = 0x11;
+}
+
+/// Conventional names of the 32 general purpose registers
+pub const REGISTER_NAMES: [&str; 32] = [
+    "$zero", "$at", "$v0", "$v1", "$a0", "$a1", "$a2", "$a3", "$t0", "$t1", "$t2", "$t3", "$t4",
+    "$t5", "$t6", "$t7", "$s0", "$s1", "$s2", "$s3", "$s4", "$s5", "$s6", "$s7", "$t8", "$t9",
+    "$k0", "$k1", "$gp", "$sp", "$fp", "$ra",
+];
+
+/// Parse a register operand: `$t0`, `$8` or `$zero`
+pub fn parse_register(name: &str) -> Option<usize> {
+    let bare = name.strip_prefix('$')?;
+    if let Ok(index) = bare.parse::<usize>() {
+        return (index < 32).then_some(index);
+    }
+    if bare == "s8" {
+        return Some(30);
+    }
+    REGISTER_NAMES.iter().position(|r| &r[1..] == bare)
+}
+
+/// Assembly operand layout of an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operands {
+    /// `rd, rs, rt`
+    RdRsRt,
+    /// `rd, rt, shamt`
+    RdRtShamt,
+    /// `rd, rt, rs`
+    RdRtRs,
+    /// `rs, rt`
+    RsRt,
+    /// `rd, rs`
+    RdRs,
+    /// `rd`
+    Rd,
+    /// `rs`
+    Rs,
+    /// `jalr rs` or `jalr rd, rs`
+    Jalr,
+    /// No operands
+    None,
+    /// `rt, rs, imm` with a sign-extended immediate
+    RtRsSimm,
+    /// `rt, rs, imm` with a zero-extended immediate
+    RtRsImm,
+    /// `rt, imm`
+    RtImm,
+    /// `rt, offset(rs)`
+    Memory,
+    /// `rs, rt, label`
+    Branch2,
+    /// `rs, label`
+    Branch1,
+    /// `label`
+    Jump,
+    /// `rt, $n` where `n` is a coprocessor 0 register or a coprocessor 1
+    /// control register, held in the `rd` field
+    CopMove,
+    /// `fd, fs, ft`
+    FdFsFt,
+    /// `fd, fs`
+    FdFs,
+    /// `fs, ft` or `cc, fs, ft`, setting a condition flag
+    FsFt,
+    /// `rt, fs`
+    RtFs,
+    /// `ft, offset(rs)`
+    FpMemory,
+    /// `label` or `cc, label`
+    Bc1,
+}
+
+/// Encoding of one native instruction
+#[derive(Clone, Copy, Debug)]
+pub struct InstrSpec {
+    pub mnemonic: &'static str,
+    pub operands: Operands,
+    pub opcode: u32,
+    /// `funct` for SPECIAL/SPECIAL2/COP1, the `rt` selector for REGIMM, the
+    /// `rs` selector for COP0, the true/false bit for `bc1t`/`bc1f`, unused otherwise
+    pub funct: u32,
+    /// `rs` format or move selector for COP1, unused otherwise
+    pub fmt: u32,
+}
+
+impl InstrSpec {
+    /// Whether this spec's fixed fields match an encoded word
+    pub fn matches(&self, word: u32) -> bool {
+        if opcode(word) != self.opcode {
+            return false;
+        }
+        match self.opcode {
+            op::SPECIAL | op::SPECIAL2 => funct(word) == self.funct,
+            op::REGIMM => rt(word) as u32 == self.funct,
+            op::COP0 => {
+                rs(word) as u32 == self.funct
+                    && (self.funct != cop0::CO || funct(word) == cop0::ERET)
+            }
+            op::COP1 => {
+                rs(word) as u32 == self.fmt
+                    && match self.operands {
+                        Operands::RtFs | Operands::CopMove => true,
+                        Operands::Bc1 => (word >> 16) & 1 == self.funct,
+                        _ => funct(word) == self.funct,
+                    }
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether a COP1 instruction's destination and sources are even/odd
+    /// register pairs holding doubles
+    pub fn fp_doubles(&self) -> (bool, bool) {
+        match (self.opcode, self.fmt) {
+            (op::LDC1 | op::SDC1, _) => (true, true),
+            (op::COP1, cop1::D) => {
+                let to_other = matches!(self.funct, cop1::CVT_S | cop1::CVT_W)
+                    || (cop1::ROUND_W..=cop1::FLOOR_W).contains(&self.funct);
+                (!to_other, true)
+            }
+            (op::COP1, cop1::S | cop1::W) => (self.funct == cop1::CVT_D, false),
+            _ => (false, false),
+        }
+    }
+}
+
+const fn spec(mnemonic: &'static str, operands: Operands, opcode: u32, funct: u32) -> InstrSpec {
+    InstrSpec {
+        mnemonic,
+        operands,
+        opcode,
+        funct,
+        fmt: 0,
+    }
+}
+
+const fn cop1(mnemonic: &'static str, operands: Operands, fmt: u32, funct: u32) -> InstrSpec {
+    InstrSpec {
+        mnemonic,
+        operands,
+        opcode: op::COP1,
+        funct,
+        fmt,
+    }
+}
+
+/// Every native instruction the core executes
+pub const INSTRUCTIONS: &[InstrSpec] = &[
+    spec("sll", Operands::RdRtShamt, op::SPECIAL, funct::SLL),
+    spec("srl", Operands::RdRtShamt, op::SPECIAL, funct::SRL),
+    spec("sra", Operands::RdRtShamt, op::SPECIAL, funct::SRA),
+    spec("sllv", Operands::RdRtRs, op::SPECIAL, funct::SLLV),
+    spec("srlv", Operands::RdRtRs, op::SPECIAL, funct::SRLV),
+    spec("srav", Operands::RdRtRs, op::SPECIAL, funct::SRAV),
+    spec("jr", Operands::Rs, op::SPECIAL, funct::JR),
+    spec("jalr", Operands::Jalr, op::SPECIAL, funct::JALR),
+    spec("movz", Operands::RdRsRt, op::SPECIAL, funct::MOVZ),
+    spec("movn", Operands::RdRsRt, op::SPECIAL, funct::MOVN),
+    spec("syscall", Operands::None, op::SPECIAL, funct::SYSCALL),
+    spec("break", Operands::None, op::SPECIAL, funct::BREAK),
+    spec("mfhi", Operands::Rd, op::SPECIAL, funct::MFHI),
+    spec("mthi", Operands::Rs, op::SPECIAL, funct::MTHI),
+    spec("mflo", Operands::Rd, op::SPECIAL, funct::MFLO),
+    spec("mtlo", Operands::Rs, op::SPECIAL, funct::MTLO),
+    spec("mult", Operands::RsRt, op::SPECIAL, funct::MULT),
+    spec("multu", Operands::RsRt, op::SPECIAL, funct::MULTU),
+    spec("div", Operands::RsRt, op::SPECIAL, funct::DIV),
+    spec("divu", Operands::RsRt, op::SPECIAL, funct::DIVU),
+    spec("add", Operands::RdRsRt, op::SPECIAL, funct::ADD),
+    spec("addu", Operands::RdRsRt, op::SPECIAL, funct::ADDU),
+    spec("sub", Operands::RdRsRt, op::SPECIAL, funct::SUB),
+    spec("subu", Operands::RdRsRt, op::SPECIAL, funct::SUBU),
+    spec("and", Operands::RdRsRt, op::SPECIAL, funct::AND),
+    spec("or", Operands::RdRsRt, op::SPECIAL, funct::OR),
+    spec("xor", Operands::RdRsRt, op::SPECIAL, funct::XOR),
+    spec("nor", Operands::RdRsRt, op::SPECIAL, funct::NOR),
+    spec("slt", Operands::RdRsRt, op::SPECIAL, funct::SLT),
+    spec("sltu", Operands::RdRsRt, op::SPECIAL, funct::SLTU),
+    spec("teq", Operands::RsRt, op::SPECIAL, funct::TEQ),
+    spec("tne", Operands::RsRt, op::SPECIAL, funct::TNE),
+    spec("madd", Operands::RsRt, op::SPECIAL2, funct2::MADD),
+    spec("maddu", Operands::RsRt, op::SPECIAL2, funct2::MADDU),
+    spec("mul", Operands::RdRsRt, op::SPECIAL2, funct2::MUL),
+    spec("msub", Operands::RsRt, op::SPECIAL2, funct2::MSUB),
+    spec("msubu", Operands::RsRt, op::SPECIAL2, funct2::MSUBU),
+    spec("clz", Operands::RdRs, op::SPECIAL2, funct2::CLZ),
+    spec("clo", Operands::RdRs, op::SPECIAL2, funct2::CLO),
+    spec("bltz", Operands::Branch1, op::REGIMM, regimm::BLTZ as u32),
+    spec("bgez", Operands::Branch1, op::REGIMM, regimm::BGEZ as u32),
+    spec(
+        "bltzal",
+        Operands::Branch1,
+        op::REGIMM,
+        regimm::BLTZAL as u32,
+    ),
+    spec(
+        "bgezal",
+        Operands::Branch1,
+        op::REGIMM,
+        regimm::BGEZAL as u32,
+    ),
+    spec("j", Operands::Jump, op::J, 0),
+    spec("jal", Operands::Jump, op::JAL, 0),
+    spec("beq", Operands::Branch2, op::BEQ, 0),
+    spec("bne", Operands::Branch2, op::BNE, 0),
+    spec("blez", Operands::Branch1, op::BLEZ, 0),
+    spec("bgtz", Operands::Branch1, op::BGTZ, 0),
+    spec("addi", Operands::RtRsSimm, op::ADDI, 0),
+    spec("addiu", Operands::RtRsSimm, op::ADDIU, 0),
+    spec("slti", Operands::RtRsSimm, op::SLTI, 0),
+    spec("sltiu", Operands::RtRsSimm, op::SLTIU, 0),
+    spec("andi", Operands::RtRsImm, op::ANDI, 0),
+    spec("ori", Operands::RtRsImm, op::ORI, 0),
+    spec("xori", Operands::RtRsImm, op::XORI, 0),
+    spec("lui", Operands::RtImm, op::LUI, 0),
+    spec("lb", Operands::Memory, op::LB, 0),
+    spec("lh", Operands::Memory, op::LH, 0),
+    spec("lw", Operands::Memory, op::LW, 0),
+    spec("lbu", Operands::Memory, op::LBU, 0),
+    spec("lhu", Operands::Memory, op::LHU, 0),
+    spec("lwl", Operands::Memory, op::LWL, 0),
+    spec("lwr", Operands::Memory, op::LWR, 0),
+    spec("sb", Operands::Memory, op::SB, 0),
+    spec("sh", Operands::Memory, op::SH, 0),
+    spec("sw", Operands::Memory, op::SW, 0),
+    spec("swl", Operands::Memory, op::SWL, 0),
+    spec("swr", Operands::Memory, op::SWR, 0),
+    spec("mfc0", Operands::CopMove, op::COP0, cop0::MF),
+    spec("mtc0", Operands::CopMove, op::COP0, cop0::MT),
+    spec("eret", Operands::None, op::COP0, cop0::CO),
+    spec("lwc1", Operands::FpMemory, op::LWC1, 0),
+    spec("ldc1", Operands::FpMemory, op::LDC1, 0),
+    spec("swc1", Operands::FpMemory, op::SWC1, 0),
+    spec("sdc1", Operands::FpMemory, op::SDC1, 0),
+    cop1("mfc1", Operands::RtFs, cop1::MF, 0),
+    cop1("mtc1", Operands::RtFs, cop1::MT, 0),
+    cop1("cfc1", Operands::CopMove, cop1::CF, 0),
+    cop1("ctc1", Operands::CopMove, cop1::CT, 0),
+    cop1("bc1f", Operands::Bc1, cop1::BC, 0),
+    cop1("bc1t", Operands::Bc1, cop1::BC, 1),
+    cop1("add.s", Operands::FdFsFt, cop1::S, cop1::ADD),
+    cop1("sub.s", Operands::FdFsFt, cop1::S, cop1::SUB),
+    cop1("mul.s", Operands::FdFsFt, cop1::S, cop1::MUL),
+    cop1("div.s", Operands::FdFsFt, cop1::S, cop1::DIV),
+    cop1("sqrt.s", Operands::FdFs, cop1::S, cop1::SQRT),
+    cop1("abs.s", Operands::FdFs, cop1::S, cop1::ABS),
+    cop1("mov.s", Operands::FdFs, cop1::S, cop1::MOV),
+    cop1("neg.s", Operands::FdFs, cop1::S, cop1::NEG),
+    cop1("round.w.s", Operands::FdFs, cop1::S, cop1::ROUND_W),
+    cop1("trunc.w.s", Operands::FdFs, cop1::S, cop1::TRUNC_W),
+    cop1("ceil.w.s", Operands::FdFs, cop1::S, cop1::CEIL_W),
+    cop1("floor.w.s", Operands::FdFs, cop1::S, cop1::FLOOR_W),
+    cop1("cvt.d.s", Operands::FdFs, cop1::S, cop1::CVT_D),
+    cop1("cvt.w.s", Operands::FdFs, cop1::S, cop1::CVT_W),
+    cop1("c.eq.s", Operands::FsFt, cop1::S, cop1::C_EQ),
+    cop1("c.lt.s", Operands::FsFt, cop1::S, cop1::C_LT),
+    cop1("c.le.s", Operands::FsFt, cop1::S, cop1::C_LE),
+    cop1("add.d", Operands::FdFsFt, cop1::D, cop1::ADD),
+    cop1("sub.d", Operands::FdFsFt, cop1::D, cop1::SUB),
+    cop1("mul.d", Operands::FdFsFt, cop1::D, cop1::MUL),
+    cop1("div.d", Operands::FdFsFt, cop1::D, cop1::DIV),
+    cop1("sqrt.d", Operands::FdFs, cop1::D, cop1::SQRT),
+    cop1("abs.d", Operands::FdFs, cop1::D, cop1::ABS),
+    cop1("mov.d", Operands::FdFs, cop1::D, cop1::MOV),
+    cop1("neg.d", Operands::FdFs, cop1::D, cop1::NEG),
+    cop1("round.w.d", Operands::FdFs, cop1::D, cop1::ROUND_W),
+    cop1("trunc.w.d", Operands::FdFs, cop1::D, cop1::TRUNC_W),
+    cop1("ceil.w.d", Operands::FdFs, cop1::D, cop1::CEIL_W),
+    cop1("floor.w.d", Operands::FdFs, cop1::D, cop1::FLOOR_W),
+    cop1("cvt.s.d", Operands::FdFs, cop1::D, cop1::CVT_S),
+    cop1("cvt.w.d", Operands::FdFs, cop1::D, cop1::CVT_W),
+    cop1("c.eq.d", Operands::FsFt, cop1::D, cop1::C_EQ),
+    cop1("c.lt.d", Operands::FsFt, cop1::D, cop1::C_LT),
+    cop1("c.le.d", Operands::FsFt, cop1::D, cop1::C_LE),
+    cop1("cvt.s.w", Operands::FdFs, cop1::W, cop1::CVT_S),
+    cop1("cvt.d.w", Operands::FdFs, cop1::W, cop1::CVT_D),
+];
+
+/// Find the native instruction an encoded word belongs to
+pub fn decode(word: u32) -> Option<&'static InstrSpec> {
+    INSTRUCTIONS.iter().find(|s| s.matches(word))
+}
+
+/// Look up a native instruction by mnemonic
+pub fn find_instruction(mnemonic: &str) -> Option<&'static InstrSpec> {
+    INSTRUCTIONS.iter().find(|s| s.mnemonic == mnemonic)
+}
+
+/// Instruction encoding format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    R,
+    I,
+    J,
+}
+
+impl Format {
+    /// Classify a word by its opcode, whether or not the instruction is implemented
+    pub fn of(word: u32) -> Self {
+        match opcode(word) {
+            op::SPECIAL | op::SPECIAL2 | op::COP0 | op::COP1 => Self::R,
+            op::J | op::JAL => Self::J,
+            _ => Self::I,
+        }
+    }
+
+    /// Fields making up this format, from the most significant bit down
+    pub fn fields(self) -> &'static [Field] {
+        match self {
+            Self::R => &[
+                Field::Opcode,
+                Field::Rs,
+                Field::Rt,
+                Field::Rd,
+                Field::Shamt,
+                Field::Funct,
+            ],
+            Self::I => &[Field::Opcode, Field::Rs, Field::Rt, Field::Imm],
+            Self::J => &[Field::Opcode, Field::Target],
+        }
+    }
+
+    /// The field that bit `bit` (0 = LSB) belongs to
+    pub fn field_at(self, bit: u32) -> Field {
+        *self
+            .fields()
+            .iter()
+            .find(|f| f.bits().contains(&bit))
+            .expect("formats cover all 32 bits")
+    }
+}
+
+/// A bit field of an encoded instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Opcode,
+    Rs,
+    Rt,
+    Rd,
+    Shamt,
+    Funct,
+    Imm,
+    Target,
+}
+
+impl Field {
+    pub const ALL: [Self; 8] = [
+        Self::Opcode,
+        Self::Rs,
+        Self::Rt,
+        Self::Rd,
+        Self::Shamt,
+        Self::Funct,
+        Self::Imm,
+        Self::Target,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Opcode => "opcode",
+            Self::Rs => "rs",
+            Self::Rt => "rt",
+            Self::Rd => "rd",
+            Self::Shamt => "shamt",
+            Self::Funct => "funct",
+            Self::Imm => "immediate",
+            Self::Target => "target",
+        }
+    }
+
+    /// Bit positions covered by the field (0 = LSB)
+    pub fn bits(self) -> std::ops::RangeInclusive<u32> {
+        match self {
+            Self::Opcode => 26..=31,
+            Self::Rs => 21..=25,
+            Self::Rt => 16..=20,
+            Self::Rd => 11..=15,
+            Self::Shamt => 6..=10,
+            Self::Funct => 0..=5,
+            Self::Imm => 0..=15,
+            Self::Target => 0..=25,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode `spec` with its fixed fields and `fill` in every field it leaves free
+    fn encode(spec: &InstrSpec, fill: u32) -> u32 {
+        let registers = fill << 21 | fill << 16 | fill << 11 | fill << 6;
+        let mut word = spec.opcode << 26;
+        match spec.opcode {
+            op::SPECIAL | op::SPECIAL2 => word |= registers | spec.funct,
+            op::REGIMM => word |= fill << 21 | spec.funct << 16 | 0x1234,
+            op::COP0 if spec.funct == cop0::CO => word |= spec.funct << 21 | cop0::ERET,
+            op::COP0 => word |= spec.funct << 21 | fill << 16 | fill << 11,
+            op::COP1 => {
+                word |= spec.fmt << 21;
+                word |= match spec.operands {
+                    Operands::RtFs | Operands::CopMove => fill << 16 | fill << 11,
+                    Operands::Bc1 => (fill & !3) << 16 | spec.funct << 16 | 0x1234,
+                    _ => (registers & 0x1F_FFC0) | spec.funct,
+                };
+            }
+            op::J | op::JAL => word |= 0x0012_3456,
+            _ => word |= fill << 21 | fill << 16 | 0xFEDC,
+        }
+        word
+    }
+
+    #[test]
+    fn every_instruction_decodes_to_itself() {
+        for spec in INSTRUCTIONS {
+            for fill in [0, 1, 6, 30, 31] {
+                let word = encode(spec, fill);
+                assert!(spec.matches(word), "{} 0x{word:08X}", spec.mnemonic);
+                let matching: Vec<_> = INSTRUCTIONS
+                    .iter()
+                    .filter(|s| s.matches(word))
+                    .map(|s| s.mnemonic)
+                    .collect();
+                assert_eq!(matching, [spec.mnemonic], "0x{word:08X}");
+                assert_eq!(decode(word).map(|s| s.mnemonic), Some(spec.mnemonic));
+            }
+            assert_eq!(
+                find_instruction(spec.mnemonic).map(|s| s.opcode),
+                Some(spec.opcode)
+            );
+        }
+    }
+
+    #[test]
+    fn fields_split_an_encoded_word() {
+        // addiu $t1, $sp, -8
+        let word = 0x27A9_FFF8;
+        assert_eq!(opcode(word), op::ADDIU);
+        assert_eq!((rs(word), rt(word)), (29, 9));
+        assert_eq!(imm(word), 0xFFF8);
+        assert_eq!(simm(word), -8i32 as u32);
+        // srl $t0, $t1, 3
+        let word = 0x0009_40C2;
+        assert_eq!(
+            (rd(word), rt(word), shamt(word), funct(word)),
+            (8, 9, 3, funct::SRL)
+        );
+        assert_eq!(Format::of(word).field_at(8), Field::Shamt);
+        assert_eq!(target(0x0C10_0008), 0x0010_0008);
+    }
+
+    #[test]
+    fn unknown_words_do_not_decode() {
+        // SPECIAL funct 0x01, REGIMM rt 0x05 and opcode 0x3F
+        for word in [0x0000_0001, 0x0405_0000, 0xFC00_0000] {
+            assert!(decode(word).is_none(), "0x{word:08X}");
+        }
+    }
+
+    #[test]
+    fn memory_access_classes() {
+        assert!(is_store(op::SWL) && !is_store(op::LWR) && !is_store(op::LW));
+        assert_eq!(access_size(op::LBU), 1);
+        assert_eq!(access_size(op::SH), 2);
+        assert_eq!(access_size(op::LWL), 4);
+        assert_eq!(access_size(op::SDC1), 8);
+        // jr $ra, mthi $ra, beq and addiu
+        assert!(is_branch(0x03E0_0008));
+        assert!(!is_branch(0x03E0_0011));
+        assert!(is_branch(0x1000_0004));
+        assert!(!is_branch(0x27A9_FFF8));
+    }
+
+    #[test]
+    fn bits_map_to_their_format_fields() {
+        // addu, addiu and jal
+        assert_eq!(Format::of(0x0109_5021), Format::R);
+        assert_eq!(Format::of(0x27A9_FFF8), Format::I);
+        assert_eq!(Format::of(0x0C10_0004), Format::J);
+        // (format, lowest and highest bit of each field in turn)
+        let table = [
+            (
+                Format::R,
+                [
+                    (0, 5, Field::Funct),
+                    (6, 10, Field::Shamt),
+                    (11, 15, Field::Rd),
+                    (16, 20, Field::Rt),
+                    (21, 25, Field::Rs),
+                    (26, 31, Field::Opcode),
+                ]
+                .as_slice(),
+            ),
+            (
+                Format::I,
+                &[
+                    (0, 15, Field::Imm),
+                    (16, 20, Field::Rt),
+                    (21, 25, Field::Rs),
+                    (26, 31, Field::Opcode),
+                ],
+            ),
+            (
+                Format::J,
+                &[(0, 25, Field::Target), (26, 31, Field::Opcode)],
+            ),
+        ];
+        for (format, fields) in table {
+            for &(low, high, field) in fields {
+                for bit in low..=high {
+                    assert_eq!(format.field_at(bit), field, "{format:?} bit {bit}");
+                }
+            }
+        }
+    }
+}
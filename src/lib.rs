@@ -1,4 +1,40 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
-pub use app::TemplateApp;
+mod assembler;
+mod bitmap;
+mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
+mod cp0;
+mod cp1;
+mod cpu;
+mod disasm;
+mod elf;
+mod image;
+mod isa;
+mod machine;
+mod memory;
+mod mmio;
+mod pipeline;
+mod syscall;
+pub use app::{MemoryRow, TemplateApp};
+pub use assembler::{
+    assemble, AsmError, Program, Segment, SegmentKind, DATA_BASE, KDATA_BASE, KTEXT_BASE, TEXT_BASE,
+};
+pub use bitmap::{BitmapConfig, BASE_ADDRESSES, DISPLAY_SIZES, UNIT_SIZES};
+pub use cache::{Cache, CacheConfig, Line, Replacement, WritePolicy};
+pub use cp0::{Cp0, EXCEPTION_VECTOR};
+pub use cp1::{Cp1, Rounding};
+pub use cpu::{Cpu, Exception, Register};
+pub use disasm::disassemble;
+pub use elf::{is_elf, read_elf, ElfError};
+pub use image::{export_image, import_image, ImageError, ImageFormat, Imported};
+pub use machine::{DataAccess, Machine, RuntimeError, Status};
+pub use memory::{AddressError, Endianness, Memory};
+pub use mmio::{
+    Devices, Uart, LED_ADDRESS, MMIO_BASE, RX_CONTROL, RX_DATA, SEGMENT_ADDRESS, SEGMENT_DIGITS,
+    SEGMENT_DP, SWITCH_ADDRESS, TX_CONTROL, TX_DATA,
+};
+pub use pipeline::{InFlight, Pipeline, Slot, STAGE_NAMES};
+pub use syscall::{Console, Outcome, SyscallError, Syscalls};
@@ -0,0 +1,329 @@
+//! The complete emulated system: CPU, memory, memory-mapped devices and syscall services
+
+use std::collections::BTreeMap;
+
+use crate::assembler::{Program, SegmentKind, TEXT_BASE};
+use crate::cache::Cache;
+use crate::cp0::EXCEPTION_VECTOR;
+use crate::cpu::{Cpu, Exception};
+use crate::isa::{self, Operands};
+use crate::memory::Memory;
+use crate::mmio::Devices;
+use crate::syscall::{Console, Outcome, SyscallError, Syscalls};
+
+/// Result of a successful `Machine::step`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    /// The program called `exit` or `exit2`
+    Exited(i32),
+    /// A syscall is waiting for console input; stepping again retries it
+    WaitingForInput,
+    /// An exception or interrupt transferred control to the kernel handler
+    Exception(Exception),
+}
+
+/// Why the machine stopped with an error
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+    Exception(Exception),
+    Syscall(SyscallError),
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exception(e) => e.fmt(f),
+            Self::Syscall(e) => e.fmt(f),
+        }
+    }
+}
+
+/// A load or store about to be made by the instruction at PC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DataAccess {
+    pub address: u32,
+    /// Bytes accessed: 1, 2, 4 or 8
+    pub size: u32,
+    pub store: bool,
+}
+
+/// CPU, memory and syscall state stepped together
+///
+/// Exceptions trap to the handler at `EXCEPTION_VECTOR` when the loaded
+/// program provides one in `.ktext`, and stop the machine otherwise.
+///
+/// Memory, the entry point, symbols, pseudo-instruction sources, cache settings and
+/// device inputs are persisted; the CPU and cache contents start from reset on every launch.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Machine {
+    #[serde(skip)]
+    pub cpu: Cpu,
+    pub memory: Memory,
+    #[serde(skip)]
+    pub syscalls: Syscalls,
+    #[serde(skip)]
+    exit_code: Option<i32>,
+    /// Where PC starts after a reset
+    pub entry: u32,
+    /// Labels or ELF symbols at each address, comma-separated where several coincide
+    pub symbols: BTreeMap<u32, String>,
+    /// Pseudo-instruction each expanded word was assembled from
+    pub pseudo_sources: BTreeMap<u32, String>,
+    /// The loaded program installed an exception handler
+    pub kernel_handler: bool,
+    /// The loaded program expects branch delay slots
+    pub delay_slots: bool,
+    /// Sees every instruction fetch
+    pub icache: Cache,
+    /// Sees every load and store
+    pub dcache: Cache,
+    pub devices: Devices,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        let mut machine = Self {
+            cpu: Cpu::default(),
+            memory: Memory::default(),
+            syscalls: Syscalls::default(),
+            exit_code: None,
+            entry: TEXT_BASE,
+            symbols: BTreeMap::new(),
+            pseudo_sources: BTreeMap::new(),
+            kernel_handler: false,
+            delay_slots: false,
+            icache: Cache::default(),
+            dcache: Cache::default(),
+            devices: Devices::default(),
+        };
+        machine.reset();
+        machine
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace memory with `program` and reset to its entry point
+    pub fn load_program(&mut self, program: &Program) {
+        self.memory.clear();
+        program.load_into(&mut self.memory);
+        self.entry = program.entry;
+        self.pseudo_sources = program.pseudo_sources.clone();
+        self.delay_slots = program.delay_slots;
+        let mut labels: Vec<(&String, &u32)> = program.labels.iter().collect();
+        labels.sort();
+        self.symbols.clear();
+        for (name, &address) in labels {
+            self.symbols
+                .entry(address)
+                .and_modify(|names| {
+                    names.push_str(", ");
+                    names.push_str(name);
+                })
+                .or_insert_with(|| name.clone());
+        }
+        self.kernel_handler = program.segments.iter().any(|s| {
+            s.kind == SegmentKind::KText
+                && (s.address..s.address.wrapping_add(s.bytes.len() as u32))
+                    .contains(&EXCEPTION_VECTOR)
+        });
+        self.reset();
+    }
+
+    /// Reset the CPU and syscall state, leaving memory untouched
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.cpu.pc = self.entry;
+        self.cpu.delay_slots = self.delay_slots;
+        self.syscalls = Syscalls::default();
+        self.exit_code = None;
+        self.devices.reset();
+        self.icache.reset();
+        self.dcache.reset();
+    }
+
+    /// Move the DIP switches, visible to the program's next load
+    pub fn set_switches(&mut self, switches: u32) {
+        self.devices.switches = switches;
+        self.devices
+            .refresh(&mut self.memory, &mut self.cpu.cp0, None);
+    }
+
+    /// Exit code, once the program has exited
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Execute one instruction, servicing `syscall` through `console`
+    pub fn step(&mut self, console: &mut dyn Console) -> Result<Status, RuntimeError> {
+        if let Some(code) = self.exit_code {
+            return Ok(Status::Exited(code));
+        }
+
+        let data_access = self.data_access();
+        self.devices
+            .refresh(&mut self.memory, &mut self.cpu.cp0, data_access);
+        if self.kernel_handler && self.cpu.cp0.interrupt_pending() {
+            return self.trap(Exception::Interrupt);
+        }
+
+        let pc = self.cpu.pc;
+
+        let exception = match self.cpu.step(&mut self.memory) {
+            Ok(()) => {
+                self.retire(pc, data_access);
+                return Ok(Status::Running);
+            }
+            Err(Exception::Syscall) => {
+                match self
+                    .syscalls
+                    .handle(&mut self.cpu, &mut self.memory, console)
+                {
+                    Ok(Outcome::Continue) => {
+                        self.retire(pc, data_access);
+                        self.cpu.advance();
+                        return Ok(Status::Running);
+                    }
+                    Ok(Outcome::Exit(code)) => {
+                        self.retire(pc, data_access);
+                        self.cpu.advance();
+                        self.exit_code = Some(code);
+                        return Ok(Status::Exited(code));
+                    }
+                    Ok(Outcome::NeedsInput) => return Ok(Status::WaitingForInput),
+                    // Services the emulator doesn't provide are left to the handler
+                    Err(SyscallError::UnknownService(_)) if self.kernel_handler => {
+                        Exception::Syscall
+                    }
+                    Err(e) => return Err(RuntimeError::Syscall(e)),
+                }
+            }
+            Err(e) => e,
+        };
+        self.trap(exception)
+    }
+
+    /// Enter the kernel handler, or stop if the program didn't install one
+    fn trap(&mut self, exception: Exception) -> Result<Status, RuntimeError> {
+        if !self.kernel_handler {
+            return Err(RuntimeError::Exception(exception));
+        }
+        self.cpu.take_exception(exception);
+        Ok(Status::Exception(exception))
+    }
+
+    /// Account for a completed instruction: advance Count, let the devices
+    /// see its data access and show its fetch and data access to the caches
+    fn retire(&mut self, pc: u32, data_access: Option<DataAccess>) {
+        self.cpu.cp0.tick();
+        self.devices.retire(&self.memory, data_access);
+        self.icache.access(pc, false);
+        if let Some(access) = data_access {
+            self.dcache.access(access.address, access.store);
+        }
+    }
+
+    /// Load or store the instruction at PC will make, if any
+    fn data_access(&self) -> Option<DataAccess> {
+        let word = self.memory.read_word(self.cpu.pc);
+        let spec = isa::decode(word)?;
+        if !matches!(spec.operands, Operands::Memory | Operands::FpMemory) {
+            return None;
+        }
+        let address = self.cpu.reg(isa::rs(word)).wrapping_add(isa::simm(word));
+        Some(DataAccess {
+            address,
+            size: isa::access_size(spec.opcode),
+            store: isa::is_store(spec.opcode),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::memory::Endianness;
+    use crate::syscall::TestConsole;
+
+    /// Assemble `source` into a fresh machine
+    fn machine(source: &str) -> Machine {
+        let program = assemble(source, Endianness::Big).expect("program assembles");
+        let mut machine = Machine::new();
+        machine.load_program(&program);
+        machine
+    }
+
+    #[test]
+    fn exception_enters_handler_and_eret_resumes() {
+        let mut machine = machine(
+            "
+            .text
+            main:   li $t0, 0x7FFFFFFF
+                    addi $t0, $t0, 1
+                    li $v0, 10
+                    syscall
+            .ktext 0x80000180
+                    mfc0 $k0, $14
+                    addiu $k0, $k0, 4
+                    mtc0 $k0, $14
+                    eret
+            ",
+        );
+        assert!(machine.kernel_handler);
+        let mut console = TestConsole::default();
+        // `li` expands to two words, so `addi` is the third
+        let overflow = machine.entry + 8;
+        let status = std::iter::repeat_with(|| machine.step(&mut console))
+            .take(10)
+            .find(|status| *status != Ok(Status::Running));
+        assert_eq!(
+            status,
+            Some(Ok(Status::Exception(Exception::IntegerOverflow)))
+        );
+        assert_eq!(machine.cpu.pc, EXCEPTION_VECTOR);
+        assert_eq!(machine.cpu.cp0.epc, overflow);
+        assert_eq!(machine.cpu.cp0.exception_code(), 12);
+        for _ in 0..4 {
+            assert_eq!(machine.step(&mut console), Ok(Status::Running));
+        }
+        assert_eq!(machine.cpu.pc, overflow + 4);
+        assert_eq!(machine.cpu.cp0.status & crate::cp0::STATUS_EXL, 0);
+        assert_eq!(machine.cpu.reg(8), 0x7FFF_FFFF);
+        assert_eq!(machine.step(&mut console), Ok(Status::Running));
+        assert_eq!(machine.step(&mut console), Ok(Status::Exited(0)));
+    }
+
+    #[test]
+    fn switches_overwrite_stores_to_their_word() {
+        let mut machine = machine(
+            "
+            .text
+            main:   lui $s0, 0xFFFF
+                    li $t0, -1
+                    sw $t0, 0x14($s0)
+                    lw $t1, 0x14($s0)
+                    li $v0, 10
+                    syscall
+            ",
+        );
+        machine.set_switches(0xA5);
+        assert_eq!(machine.memory.read_word(crate::mmio::SWITCH_ADDRESS), 0xA5);
+        let mut console = TestConsole::default();
+        for _ in 0..20 {
+            if machine.step(&mut console) != Ok(Status::Running) {
+                break;
+            }
+        }
+        assert_eq!(machine.exit_code(), Some(0));
+        assert_eq!(machine.cpu.reg(9), 0xA5);
+        // Moving the switches is visible before the next instruction
+        machine.set_switches(0x0F);
+        assert_eq!(machine.memory.read_word(crate::mmio::SWITCH_ADDRESS), 0x0F);
+    }
+}
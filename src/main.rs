@@ -6,6 +6,13 @@
 fn main() -> eframe::Result {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    // `run` selects the headless runner instead of the GUI; anything else,
+    // such as arguments the platform adds, is left to eframe
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "run") {
+        std::process::exit(eframe_template::cli::run(&args));
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -0,0 +1,329 @@
+//! Sparse, paged memory covering the full 32-bit address space
+
+use std::collections::HashMap;
+
+/// log2 of the page size in bytes (4 KiB pages)
+const PAGE_SHIFT: u32 = 12;
+const PAGE_WORDS: usize = 1 << (PAGE_SHIFT - 2);
+
+/// Byte order of words in memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Endianness {
+    /// Byte 0 of a word is its most significant byte (MIPS default)
+    #[default]
+    Big,
+    /// Byte 0 of a word is its least significant byte
+    Little,
+}
+
+impl Endianness {
+    /// Bit offset of the byte at `address` within its word
+    pub fn byte_shift(self, address: u32) -> u32 {
+        match self {
+            Self::Big => (3 - (address & 3)) * 8,
+            Self::Little => (address & 3) * 8,
+        }
+    }
+}
+
+/// A halfword or word access that was not naturally aligned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddressError(pub u32);
+
+impl std::fmt::Display for AddressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "misaligned access at 0x{:08X}", self.0)
+    }
+}
+
+/// Word-addressed memory where only pages that were written take up space
+///
+/// Unwritten addresses read as zero, so every 32-bit address is valid.
+#[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
+#[serde(from = "MemoryImage", into = "MemoryImage")]
+pub struct Memory {
+    pages: HashMap<u32, Box<[u32; PAGE_WORDS]>>,
+    endianness: Endianness,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn split(address: u32) -> (u32, usize) {
+        (
+            address >> PAGE_SHIFT,
+            ((address >> 2) as usize) & (PAGE_WORDS - 1),
+        )
+    }
+
+    /// Read the word containing `address` (the low two bits are ignored)
+    ///
+    /// Unlike `load_word` this never fails; it is how the LED grid sees memory.
+    pub fn read_word(&self, address: u32) -> u32 {
+        let (page, offset) = Self::split(address);
+        self.pages.get(&page).map_or(0, |p| p[offset])
+    }
+
+    /// Write the word containing `address` (the low two bits are ignored)
+    pub fn write_word(&mut self, address: u32, value: u32) {
+        let (page, offset) = Self::split(address);
+        if value == 0 && !self.pages.contains_key(&page) {
+            // Untouched pages already read as zero
+            return;
+        }
+        self.pages
+            .entry(page)
+            .or_insert_with(|| Box::new([0; PAGE_WORDS]))[offset] = value;
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Change how bytes and halfwords map onto words; stored words are unchanged
+    pub fn set_endianness(&mut self, endianness: Endianness) {
+        self.endianness = endianness;
+    }
+
+    pub fn load_byte(&self, address: u32) -> u8 {
+        (self.read_word(address) >> self.endianness.byte_shift(address)) as u8
+    }
+
+    pub fn load_half(&self, address: u32) -> Result<u16, AddressError> {
+        if address % 2 != 0 {
+            return Err(AddressError(address));
+        }
+        Ok((self.read_word(address) >> self.half_shift(address)) as u16)
+    }
+
+    pub fn load_word(&self, address: u32) -> Result<u32, AddressError> {
+        if address % 4 != 0 {
+            return Err(AddressError(address));
+        }
+        Ok(self.read_word(address))
+    }
+
+    pub fn store_byte(&mut self, address: u32, value: u8) {
+        let shift = self.endianness.byte_shift(address);
+        let word = self.read_word(address);
+        self.write_word(
+            address,
+            (word & !(0xFF << shift)) | ((value as u32) << shift),
+        );
+    }
+
+    pub fn store_half(&mut self, address: u32, value: u16) -> Result<(), AddressError> {
+        if address % 2 != 0 {
+            return Err(AddressError(address));
+        }
+        let shift = self.half_shift(address);
+        let word = self.read_word(address);
+        self.write_word(
+            address,
+            (word & !(0xFFFF << shift)) | ((value as u32) << shift),
+        );
+        Ok(())
+    }
+
+    pub fn store_word(&mut self, address: u32, value: u32) -> Result<(), AddressError> {
+        if address % 4 != 0 {
+            return Err(AddressError(address));
+        }
+        self.write_word(address, value);
+        Ok(())
+    }
+
+    /// Bit offset of the aligned halfword at `address` within its word
+    fn half_shift(&self, address: u32) -> u32 {
+        // The halfword's LSB sits in whichever of its two byte lanes is lower
+        let first = self.endianness.byte_shift(address);
+        let second = self.endianness.byte_shift(address | 1);
+        first.min(second)
+    }
+
+    /// Write consecutive words starting at `address`
+    pub fn load_words(&mut self, address: u32, words: &[u32]) {
+        for (i, &word) in words.iter().enumerate() {
+            self.write_word(address.wrapping_add(i as u32 * 4), word);
+        }
+    }
+
+    /// Release every page, returning all of memory to zero
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Number of pages currently backed by storage
+    pub fn mapped_pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+/// Zero words allowed inside a run before it is split in two
+const MAX_ZERO_GAP: usize = 4;
+
+/// Persisted form of `Memory`: only runs of non-zero words are stored, so a
+/// mostly empty 4 GiB address space costs a few bytes
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct MemoryImage {
+    endianness: Endianness,
+    /// `(start address, words)` pairs in ascending address order
+    runs: Vec<(u32, Vec<u32>)>,
+}
+
+impl From<Memory> for MemoryImage {
+    fn from(memory: Memory) -> Self {
+        let mut pages: Vec<_> = memory.pages.iter().collect();
+        pages.sort_unstable_by_key(|(page, _)| **page);
+
+        let mut runs: Vec<(u32, Vec<u32>)> = Vec::new();
+        let mut next_address: Option<u32> = None;
+        for (page, words) in pages {
+            for (offset, &word) in words.iter().enumerate() {
+                if word == 0 {
+                    continue;
+                }
+                let address = (page << PAGE_SHIFT) | ((offset as u32) << 2);
+                // Zero words between the end of the last run and this word
+                let gap = next_address.map(|next| (address.wrapping_sub(next) / 4) as usize);
+                match runs.last_mut() {
+                    Some((_, run)) if gap.is_some_and(|gap| gap <= MAX_ZERO_GAP) => {
+                        run.extend(std::iter::repeat(0).take(gap.unwrap_or(0)));
+                        run.push(word);
+                    }
+                    _ => runs.push((address, vec![word])),
+                }
+                next_address = Some(address.wrapping_add(4));
+            }
+        }
+
+        Self {
+            endianness: memory.endianness,
+            runs,
+        }
+    }
+}
+
+impl From<MemoryImage> for Memory {
+    fn from(image: MemoryImage) -> Self {
+        let mut memory = Self::new();
+        memory.endianness = image.endianness;
+        for (address, words) in &image.runs {
+            memory.load_words(*address, words);
+        }
+        memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_gaps_split_runs() {
+        let mut memory = Memory::new();
+        memory.write_word(0x1000, 1);
+        memory.write_word(0x1000 + 4 * (MAX_ZERO_GAP as u32 + 1), 2);
+        memory.write_word(0x2000, 3);
+        // Across a page boundary the run continues
+        memory.write_word(0x0040_0000 - 4, 4);
+        memory.write_word(0x0040_0000, 5);
+        let image = MemoryImage::from(memory);
+        let mut gap = vec![0; MAX_ZERO_GAP];
+        gap.insert(0, 1);
+        gap.push(2);
+        assert_eq!(
+            image.runs,
+            [(0x1000, gap), (0x2000, vec![3]), (0x003F_FFFC, vec![4, 5])]
+        );
+    }
+
+    #[test]
+    fn memory_survives_persistence() {
+        let mut memory = Memory::new();
+        memory.set_endianness(Endianness::Little);
+        let words = [
+            (0x0040_0000, 0x2408_0007),
+            (0x0040_0004, 0x0000_000C),
+            (0x1001_0000, 0xDEAD_BEEF),
+            (0x1001_0010, 1),
+            (0x7FFF_EFFC, 0xFFFF_FFFF),
+            (0xFFFF_0010, 0x8000_0001),
+        ];
+        for (address, word) in words {
+            memory.write_word(address, word);
+        }
+
+        let text = ron::to_string(&memory).expect("memory serializes");
+        assert!(text.len() < 200, "{text}");
+        let restored: Memory = ron::from_str(&text).expect("memory deserializes");
+        assert_eq!(restored.endianness(), Endianness::Little);
+        for (address, word) in words {
+            assert_eq!(restored.read_word(address), word, "0x{address:08X}");
+        }
+        assert_eq!(restored.read_word(0x1001_0004), 0);
+        assert_eq!(restored.mapped_pages(), memory.mapped_pages());
+    }
+
+    #[test]
+    fn bytes_and_halves_in_either_byte_order() {
+        // (byte order, bytes 0-3 of 0x11223344, halves at 0 and 2)
+        let table = [
+            (Endianness::Big, [0x11, 0x22, 0x33, 0x44], [0x1122, 0x3344]),
+            (
+                Endianness::Little,
+                [0x44, 0x33, 0x22, 0x11],
+                [0x3344, 0x1122],
+            ),
+        ];
+        for (endianness, bytes, halves) in table {
+            let mut memory = Memory::new();
+            memory.set_endianness(endianness);
+            memory.write_word(0x100, 0x1122_3344);
+            for (offset, byte) in bytes.into_iter().enumerate() {
+                assert_eq!(
+                    memory.load_byte(0x100 + offset as u32),
+                    byte,
+                    "{endianness:?}"
+                );
+            }
+            assert_eq!(memory.load_half(0x100), Ok(halves[0]), "{endianness:?}");
+            assert_eq!(memory.load_half(0x102), Ok(halves[1]), "{endianness:?}");
+            assert_eq!(
+                (memory.half_shift(0x100), memory.half_shift(0x102)),
+                match endianness {
+                    Endianness::Big => (16, 0),
+                    Endianness::Little => (0, 16),
+                }
+            );
+
+            memory.store_byte(0x101, 0xAA);
+            memory.store_half(0x102, 0xBBCC).unwrap();
+            let expected = match endianness {
+                Endianness::Big => 0x11AA_BBCC,
+                Endianness::Little => 0xBBCC_AA44,
+            };
+            assert_eq!(memory.read_word(0x100), expected, "{endianness:?}");
+        }
+    }
+
+    #[test]
+    fn misaligned_halves_and_words_are_address_errors() {
+        let mut memory = Memory::new();
+        for address in [0x101, 0x103] {
+            assert_eq!(memory.load_half(address), Err(AddressError(address)));
+            assert_eq!(memory.store_half(address, 1), Err(AddressError(address)));
+        }
+        for address in [0x101, 0x102, 0x103] {
+            assert_eq!(memory.load_word(address), Err(AddressError(address)));
+            assert_eq!(memory.store_word(address, 1), Err(AddressError(address)));
+        }
+        // Failed stores leave memory untouched
+        assert_eq!(memory.mapped_pages(), 0);
+        memory.store_word(0x104, 7).unwrap();
+        assert_eq!(memory.load_word(0x104), Ok(7));
+    }
+}
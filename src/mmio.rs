@@ -0,0 +1,321 @@
+//! Memory-mapped devices in the top page of the address space, as in MARS
+//!
+//! Devices are plain words of memory: the machine refreshes their inputs
+//! before each instruction and the app reads their outputs back, so loads,
+//! stores, the LED grid and memory images all see the same values.
+
+use std::collections::VecDeque;
+
+use crate::cp0::Cp0;
+use crate::machine::DataAccess;
+use crate::memory::Memory;
+
+/// First address of the memory-mapped I/O page
+pub const MMIO_BASE: u32 = 0xFFFF_0000;
+
+/// Receiver control: bit 0 ready, bit 1 interrupt enable
+pub const RX_CONTROL: u32 = 0xFFFF_0000;
+/// Receiver data: the received byte; loading it clears receiver ready
+pub const RX_DATA: u32 = 0xFFFF_0004;
+/// Transmitter control: bit 0 ready, bit 1 interrupt enable
+pub const TX_CONTROL: u32 = 0xFFFF_0008;
+/// Transmitter data: storing a byte while ready sends it
+pub const TX_DATA: u32 = 0xFFFF_000C;
+
+/// Word whose 32 bits light the LED bank, bit 31 leftmost
+pub const LED_ADDRESS: u32 = 0xFFFF_0010;
+
+/// Word that reads the 32 DIP switches; stores to it are overwritten
+pub const SWITCH_ADDRESS: u32 = 0xFFFF_0014;
+
+/// First of the bytes driving the seven-segment digits; the byte at
+/// `SEGMENT_ADDRESS + n` drives digit n, and digit 0 is rightmost
+pub const SEGMENT_ADDRESS: u32 = 0xFFFF_0018;
+pub const SEGMENT_DIGITS: u32 = 8;
+
+/// Segment bits of a digit byte: bit 0 is segment a through bit 6 for g
+pub const SEGMENT_DP: u8 = 1 << 7;
+
+/// Segments a–g lit for each hex digit
+const HEX_SEGMENTS: [u8; 16] = [
+    0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71,
+];
+
+/// UART control register bits
+const READY: u32 = 1 << 0;
+const INTERRUPT_ENABLE: u32 = 1 << 1;
+
+/// Interrupt lines of the receiver and transmitter, Cause bits 8 and 9 as in MARS
+pub const RX_INTERRUPT: u32 = 0;
+pub const TX_INTERRUPT: u32 = 1;
+
+/// Instructions the transmitter stays busy after sending a byte, as in MARS
+const TX_DELAY: u32 = 5;
+
+/// A MARS-style keyboard and display terminal
+///
+/// The receiver takes bytes from `input` one at a time; the transmitter
+/// appends each byte it sends to `output`. Either side requests an interrupt
+/// for as long as it is ready with its interrupt enable bit set.
+#[derive(Clone, Debug, Default)]
+pub struct Uart {
+    /// Keys typed but not yet received
+    pub input: VecDeque<u8>,
+    /// Transmitted text not yet taken by the terminal
+    pub output: String,
+    rx_data: u8,
+    rx_ready: bool,
+    rx_interrupts: bool,
+    /// The program loaded receiver control, so it is waiting for input
+    rx_polled: bool,
+    tx_interrupts: bool,
+    /// Instructions until the transmitter is ready again
+    tx_busy: u32,
+}
+
+impl Uart {
+    /// Whether the program is waiting for a key that hasn't been typed yet
+    pub fn wants_input(&self) -> bool {
+        self.input.is_empty() && !self.rx_ready && (self.rx_interrupts || self.rx_polled)
+    }
+
+    /// Queue typed text for the receiver
+    pub fn type_text(&mut self, text: &str) {
+        self.input.extend(text.bytes());
+    }
+
+    /// Take everything transmitted since the last call
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    fn tx_ready(&self) -> bool {
+        self.tx_busy == 0
+    }
+
+    fn refresh(&mut self, memory: &mut Memory, cp0: &mut Cp0, access: Option<DataAccess>) {
+        if !self.rx_ready {
+            if let Some(byte) = self.input.pop_front() {
+                self.rx_data = byte;
+                self.rx_ready = true;
+                self.rx_polled = false;
+            }
+        }
+        let control = |ready: bool, interrupts: bool| {
+            (if ready { READY } else { 0 }) | (if interrupts { INTERRUPT_ENABLE } else { 0 })
+        };
+        let rx_control = control(self.rx_ready, self.rx_interrupts);
+        let tx_control = control(self.tx_ready(), self.tx_interrupts);
+        present(memory, RX_CONTROL, rx_control as u8, access);
+        present(memory, RX_DATA, self.rx_data, access);
+        present(memory, TX_CONTROL, tx_control as u8, access);
+        cp0.set_interrupt(RX_INTERRUPT, self.rx_ready && self.rx_interrupts);
+        cp0.set_interrupt(TX_INTERRUPT, self.tx_ready() && self.tx_interrupts);
+    }
+
+    /// React to a completed load or store
+    fn access(&mut self, memory: &Memory, access: DataAccess) {
+        match (access.address & !3, access.store) {
+            (RX_CONTROL, false) => self.rx_polled = true,
+            (RX_DATA, false) => self.rx_ready = false,
+            (RX_CONTROL, true) => {
+                self.rx_interrupts = stored(memory, access) & INTERRUPT_ENABLE as u8 != 0;
+            }
+            (TX_CONTROL, true) => {
+                self.tx_interrupts = stored(memory, access) & INTERRUPT_ENABLE as u8 != 0;
+            }
+            // A byte sent while the transmitter is busy is lost
+            (TX_DATA, true) if self.tx_ready() => {
+                let byte = stored(memory, access);
+                self.output.push(char::from(byte));
+                self.tx_busy = TX_DELAY;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        self.tx_busy = self.tx_busy.saturating_sub(1);
+    }
+}
+
+/// Put the value of a byte-wide register where the coming load will find it:
+/// the low byte of the word for word loads, or the lane a byte or halfword
+/// load of the register reads, whatever the byte order
+///
+/// `lwl` and `lwr` count as word loads and see the same word `lw` does.
+fn present(memory: &mut Memory, register: u32, value: u8, access: Option<DataAccess>) {
+    memory.write_word(register, value as u32);
+    let Some(access) = access.filter(|a| !a.store && a.address & !3 == register) else {
+        return;
+    };
+    match access.size {
+        1 => {
+            memory.write_word(register, 0);
+            memory.store_byte(access.address, value);
+        }
+        2 if access.address % 2 == 0 => {
+            memory.write_word(register, 0);
+            let _ = memory.store_half(access.address, value as u16);
+        }
+        _ => {}
+    }
+}
+
+/// Low byte of the value a completed store wrote to a byte-wide register
+fn stored(memory: &Memory, access: DataAccess) -> u8 {
+    match access.size {
+        1 => memory.load_byte(access.address),
+        2 => memory.load_half(access.address).unwrap_or(0) as u8,
+        _ => memory.read_word(access.address) as u8,
+    }
+}
+
+/// State of the lab-board peripherals that doesn't live in memory
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Devices {
+    /// DIP switch positions, one bit per switch
+    pub switches: u32,
+    /// Seven-segment digits show the low nibble of their byte as a hex
+    /// digit instead of lighting its bits as raw segments
+    pub hex_decode: bool,
+    #[serde(skip)]
+    pub uart: Uart,
+}
+
+impl Devices {
+    /// Copy device inputs and status into their memory-mapped words and
+    /// raise or clear their interrupt lines
+    ///
+    /// `access` is the load or store the next instruction will make, so that
+    /// byte registers can be read with byte loads in either byte order.
+    pub fn refresh(&mut self, memory: &mut Memory, cp0: &mut Cp0, access: Option<DataAccess>) {
+        memory.write_word(SWITCH_ADDRESS, self.switches);
+        self.uart.refresh(memory, cp0, access);
+    }
+
+    /// Let the devices see a retired instruction and its data access
+    pub fn retire(&mut self, memory: &Memory, data_access: Option<DataAccess>) {
+        self.uart.tick();
+        if let Some(access) = data_access {
+            self.uart.access(memory, access);
+        }
+    }
+
+    /// Return the devices to their power-on state, keeping the switches
+    /// where they are and the terminal's pending keys and output
+    pub fn reset(&mut self) {
+        self.uart = Uart {
+            input: std::mem::take(&mut self.uart.input),
+            output: self.uart.take_output(),
+            ..Uart::default()
+        };
+    }
+
+    /// Bits the program last stored to the LED bank
+    pub fn leds(&self, memory: &Memory) -> u32 {
+        memory.read_word(LED_ADDRESS)
+    }
+
+    /// Segments lit on seven-segment `digit`; the decimal point is bit 7 in both modes
+    pub fn segments(&self, memory: &Memory, digit: u32) -> u8 {
+        let byte = memory.load_byte(SEGMENT_ADDRESS.wrapping_add(digit));
+        if self.hex_decode {
+            HEX_SEGMENTS[(byte & 0xF) as usize] | (byte & SEGMENT_DP)
+        } else {
+            byte
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::machine::{Machine, Status};
+    use crate::memory::Endianness;
+    use crate::syscall::TestConsole;
+
+    /// Echo two typed keys through the terminal using `load` and `store`
+    fn echo(load: &str, store: &str, endianness: Endianness) -> String {
+        let source = format!(
+            "
+            .text
+            main:   lui $s0, 0xFFFF
+                    li $s1, 2
+            poll:   {load} $t0, 0($s0)
+                    andi $t0, $t0, 1
+                    beq $t0, $zero, poll
+                    {load} $a0, 4($s0)
+            wait:   {load} $t0, 8($s0)
+                    andi $t0, $t0, 1
+                    beq $t0, $zero, wait
+                    {store} $a0, 12($s0)
+                    addi $s1, $s1, -1
+                    bne $s1, $zero, poll
+                    li $v0, 10
+                    syscall
+            "
+        );
+        let program = assemble(&source, endianness).expect("program assembles");
+        let mut machine = Machine::new();
+        machine.load_program(&program);
+        machine.devices.uart.type_text("ok");
+        for _ in 0..1000 {
+            if machine.step(&mut TestConsole::default()) != Ok(Status::Running) {
+                break;
+            }
+        }
+        machine.devices.uart.take_output()
+    }
+
+    #[test]
+    fn byte_and_word_access_in_either_byte_order() {
+        for endianness in [Endianness::Big, Endianness::Little] {
+            assert_eq!(echo("lw", "sw", endianness), "ok");
+            assert_eq!(echo("lbu", "sb", endianness), "ok");
+            assert_eq!(echo("lhu", "sh", endianness), "ok");
+        }
+    }
+
+    #[test]
+    fn seven_segment_digits() {
+        let mut devices = Devices::default();
+        let mut memory = Memory::new();
+        // (stored byte, raw segments, hex-decoded segments)
+        let table = [
+            (0x00, 0x00, 0x3F),
+            (0x01, 0x01, 0x06),
+            (0x08, 0x08, 0x7F),
+            (0x0A, 0x0A, 0x77),
+            (0x0F, 0x0F, 0x71),
+            (0x5B, 0x5B, 0x7C),
+            (0x83, 0x83, 0x4F | SEGMENT_DP),
+            (0xFF, 0xFF, 0x71 | SEGMENT_DP),
+        ];
+        for (digit, (byte, raw, decoded)) in table.into_iter().enumerate() {
+            let digit = digit as u32 % SEGMENT_DIGITS;
+            memory.store_byte(SEGMENT_ADDRESS + digit, byte);
+            devices.hex_decode = false;
+            assert_eq!(devices.segments(&memory, digit), raw, "0x{byte:02X}");
+            devices.hex_decode = true;
+            assert_eq!(devices.segments(&memory, digit), decoded, "0x{byte:02X}");
+        }
+    }
+
+    #[test]
+    fn digits_follow_byte_addresses_in_either_byte_order() {
+        let devices = Devices::default();
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let mut memory = Memory::new();
+            memory.set_endianness(endianness);
+            memory.store_byte(SEGMENT_ADDRESS + 1, 0x06);
+            memory.store_byte(SEGMENT_ADDRESS + 7, 0x80);
+            let lit: Vec<u8> = (0..SEGMENT_DIGITS)
+                .map(|digit| devices.segments(&memory, digit))
+                .collect();
+            assert_eq!(lit, [0, 0x06, 0, 0, 0, 0, 0, 0x80], "{endianness:?}");
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! Classic five-stage (IF/ID/EX/MEM/WB) pipeline timing model
+//!
+//! The functional core still does the work: an instruction executes when it
+//! reaches EX, which is also where branches and jumps resolve. The model
+//! tracks which instruction occupies each stage, inserts bubbles for data
+//! hazards and flushes the two younger stages whenever the executed
+//! instruction sends PC somewhere other than the instruction behind it.
+
+use std::collections::VecDeque;
+
+use crate::isa::{self, funct, op, Operands};
+use crate::machine::{Machine, RuntimeError, Status};
+use crate::syscall::Console;
+
+/// Stage names, oldest instruction last
+pub const STAGE_NAMES: [&str; 5] = ["IF", "ID", "EX", "MEM", "WB"];
+
+const FETCH: usize = 0;
+const DECODE: usize = 1;
+const EXECUTE: usize = 2;
+const MEMORY: usize = 3;
+const WRITE_BACK: usize = 4;
+
+/// Cycles of stage history kept for the pipeline diagram
+const HISTORY_LEN: usize = 8;
+
+/// Dependency mask bits for HI and LO after the 32 GPRs, then the 32 FPRs
+/// and FCSR
+const HI: u128 = 1 << 32;
+const LO: u128 = 1 << 33;
+const FPR_BASE: usize = 34;
+const FCSR: u128 = 1 << (FPR_BASE + 32);
+
+/// An instruction travelling down the pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InFlight {
+    pub pc: u32,
+    pub word: u32,
+    /// Registers read and written, one bit per GPR and FPR plus `HI`, `LO` and `FCSR`
+    reads: u128,
+    writes: u128,
+    is_load: bool,
+    /// `rs` and `rt` as read in EX, after any forwarding
+    operands: [u32; 2],
+    /// ALU output: the effective address for loads and stores
+    alu: u32,
+    /// Value written back to the destination register
+    result: u32,
+}
+
+impl InFlight {
+    fn new(pc: u32, word: u32) -> Self {
+        let (reads, writes) = register_usage(word);
+        Self {
+            pc,
+            word,
+            reads,
+            writes,
+            is_load: matches!(
+                isa::opcode(word),
+                op::LB
+                    | op::LH
+                    | op::LWL
+                    | op::LW
+                    | op::LBU
+                    | op::LHU
+                    | op::LWR
+                    | op::LWC1
+                    | op::LDC1
+            ),
+            operands: [0; 2],
+            alu: 0,
+            result: 0,
+        }
+    }
+}
+
+/// What occupies a pipeline stage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Slot {
+    #[default]
+    Empty,
+    /// Inserted into EX while a hazard holds the younger stages
+    Bubble,
+    /// Squashed because an older instruction changed PC; holds its address
+    Flushed(u32),
+    Instruction(InFlight),
+}
+
+/// Pipeline state layered over a `Machine`
+///
+/// Only the forwarding switch is persisted.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Pipeline {
+    /// Forward EX/MEM and MEM/WB results so only load-use hazards stall
+    pub forwarding: bool,
+    #[serde(skip)]
+    slots: [Slot; 5],
+    #[serde(skip)]
+    fetch_pc: u32,
+    /// The instruction in EX is a syscall waiting for console input
+    #[serde(skip)]
+    waiting: bool,
+    #[serde(skip)]
+    history: VecDeque<[Slot; 5]>,
+    #[serde(skip)]
+    pub cycles: u64,
+    #[serde(skip)]
+    pub retired: u64,
+    #[serde(skip)]
+    pub stalls: u64,
+    #[serde(skip)]
+    pub flushes: u64,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            forwarding: true,
+            slots: [Slot::Empty; 5],
+            fetch_pc: 0,
+            waiting: false,
+            history: VecDeque::new(),
+            cycles: 0,
+            retired: 0,
+            stalls: 0,
+            flushes: 0,
+        }
+    }
+}
+
+impl Pipeline {
+    /// Empty every stage and start fetching at `pc`
+    pub fn reset(&mut self, pc: u32) {
+        *self = Self {
+            forwarding: self.forwarding,
+            fetch_pc: pc,
+            ..Self::default()
+        };
+    }
+
+    /// Contents of each stage, in `STAGE_NAMES` order
+    pub fn slots(&self) -> &[Slot; 5] {
+        &self.slots
+    }
+
+    /// Stage contents for recent cycles, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &[Slot; 5]> {
+        self.history.iter()
+    }
+
+    /// Names of the stages holding the instruction at `address`
+    pub fn stages_at(&self, address: u32) -> impl Iterator<Item = &'static str> + '_ {
+        self.slots
+            .iter()
+            .zip(STAGE_NAMES)
+            .filter(move |(slot, _)| matches!(slot, Slot::Instruction(i) if i.pc == address))
+            .map(|(_, name)| name)
+    }
+
+    /// Values latched in the IF/ID, ID/EX, EX/MEM and MEM/WB registers
+    pub fn registers(&self) -> [(&'static str, u32); 8] {
+        let latched = |stage: usize| match self.slots[stage] {
+            Slot::Instruction(i) => Some(i),
+            _ => None,
+        };
+        let decode = latched(DECODE);
+        let execute = latched(EXECUTE);
+        let memory = latched(MEMORY);
+        let write_back = latched(WRITE_BACK);
+        [
+            ("IF/ID IR", decode.map_or(0, |i| i.word)),
+            ("IF/ID PC+4", decode.map_or(0, |i| i.pc.wrapping_add(4))),
+            ("ID/EX A", execute.map_or(0, |i| i.operands[0])),
+            ("ID/EX B", execute.map_or(0, |i| i.operands[1])),
+            ("ID/EX Imm", execute.map_or(0, |i| isa::simm(i.word))),
+            ("EX/MEM ALU", memory.map_or(0, |i| i.alu)),
+            ("EX/MEM B", memory.map_or(0, |i| i.operands[1])),
+            ("MEM/WB Data", write_back.map_or(0, |i| i.result)),
+        ]
+    }
+
+    /// Advance one clock cycle, executing whatever reaches EX
+    pub fn cycle(
+        &mut self,
+        machine: &mut Machine,
+        console: &mut dyn Console,
+    ) -> Result<Status, RuntimeError> {
+        if let Some(code) = machine.exit_code() {
+            return Ok(Status::Exited(code));
+        }
+
+        // A syscall waiting for input holds the whole pipeline
+        if !self.waiting {
+            let stall = self.hazard();
+            self.slots[WRITE_BACK] = self.slots[MEMORY];
+            self.slots[MEMORY] = self.slots[EXECUTE];
+            if stall {
+                self.slots[EXECUTE] = Slot::Bubble;
+                self.stalls += 1;
+            } else {
+                self.slots[EXECUTE] = self.slots[DECODE];
+                self.slots[DECODE] = self.slots[FETCH];
+                self.slots[FETCH] = self.fetch(machine);
+            }
+            if matches!(self.slots[WRITE_BACK], Slot::Instruction(_)) {
+                self.retired += 1;
+            }
+        }
+        self.cycles += 1;
+
+        let status = self.execute(machine, console);
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.slots);
+        status
+    }
+
+    fn fetch(&mut self, machine: &Machine) -> Slot {
+        let pc = self.fetch_pc;
+        self.fetch_pc = pc.wrapping_add(4);
+        Slot::Instruction(InFlight::new(pc, machine.memory.read_word(pc)))
+    }
+
+    /// Whether the instruction in ID must wait for a result not yet available
+    fn hazard(&self) -> bool {
+        let Slot::Instruction(decode) = self.slots[DECODE] else {
+            return false;
+        };
+        let produces = |stage: usize, loads_only: bool| {
+            matches!(self.slots[stage], Slot::Instruction(producer)
+                if producer.writes & decode.reads != 0 && (producer.is_load || !loads_only))
+        };
+        if self.forwarding {
+            // Only a load's data arrives too late to forward into EX
+            produces(EXECUTE, true)
+        } else {
+            // The register file writes in the first half of WB and reads in
+            // the second half of ID, so only EX and MEM conflict
+            produces(EXECUTE, false) || produces(MEMORY, false)
+        }
+    }
+
+    /// Run the instruction in EX on the functional core
+    fn execute(
+        &mut self,
+        machine: &mut Machine,
+        console: &mut dyn Console,
+    ) -> Result<Status, RuntimeError> {
+        let Slot::Instruction(mut instruction) = self.slots[EXECUTE] else {
+            return Ok(Status::Running);
+        };
+        if machine.cpu.pc != instruction.pc {
+            // PC was changed behind the pipeline's back; refetch from it
+            self.slots[EXECUTE] = Slot::Flushed(instruction.pc);
+            self.flush(machine.cpu.pc);
+            return Ok(Status::Running);
+        }
+
+        let word = instruction.word;
+        instruction.operands = [
+            machine.cpu.reg(isa::rs(word)),
+            machine.cpu.reg(isa::rt(word)),
+        ];
+        let status = machine.step(console)?;
+        self.waiting = status == Status::WaitingForInput;
+        if self.waiting {
+            return Ok(status);
+        }
+
+        let destination = (0..FPR_BASE + 32).find(|&r| instruction.writes & (1 << r) != 0);
+        instruction.result = match destination {
+            Some(r) if r < 32 => machine.cpu.reg(r),
+            Some(r) if r >= FPR_BASE => machine.cpu.cp1.regs[r - FPR_BASE],
+            _ if instruction.writes & LO != 0 => machine.cpu.lo,
+            _ => 0,
+        };
+        instruction.alu = if matches!(isa::decode(word),
+            Some(spec) if matches!(spec.operands, Operands::Memory | Operands::FpMemory))
+        {
+            instruction.operands[0].wrapping_add(isa::simm(word))
+        } else {
+            instruction.result
+        };
+        self.slots[EXECUTE] = Slot::Instruction(instruction);
+
+        let next_in_order =
+            matches!(self.slots[DECODE], Slot::Instruction(next) if next.pc == machine.cpu.pc);
+        if !next_in_order && matches!(status, Status::Running | Status::Exception(_)) {
+            self.flush(machine.cpu.pc);
+        }
+        Ok(status)
+    }
+
+    /// Squash IF and ID and fetch from `pc` next cycle
+    fn flush(&mut self, pc: u32) {
+        for slot in &mut self.slots[FETCH..=DECODE] {
+            if let Slot::Instruction(squashed) = *slot {
+                *slot = Slot::Flushed(squashed.pc);
+            }
+        }
+        self.fetch_pc = pc;
+        self.flushes += 1;
+    }
+}
+
+/// Registers an instruction reads and writes, as masks with one bit per GPR
+/// and FPR plus `HI`, `LO` and `FCSR`; `$zero` never creates a dependency
+fn register_usage(word: u32) -> (u128, u128) {
+    let Some(spec) = isa::decode(word) else {
+        return (0, 0);
+    };
+    let bit = |r: usize| 1u128 << r;
+    let (rs, rt, rd) = (bit(isa::rs(word)), bit(isa::rt(word)), bit(isa::rd(word)));
+    // A double covers an even/odd pair; an odd register names the pair
+    // below it, as in `Cp1`, so the mask never reaches past the FPRs
+    let (double_result, double_sources) = spec.fp_doubles();
+    let fpr = |r: usize, double: bool| {
+        if double {
+            bit(FPR_BASE + (r & !1)) | bit(FPR_BASE + (r | 1))
+        } else {
+            bit(FPR_BASE + r)
+        }
+    };
+    let (ft, fs, fd) = (
+        fpr(isa::rt(word), double_sources),
+        fpr(isa::rd(word), double_sources),
+        fpr(isa::shamt(word) as usize, double_result),
+    );
+    let (reads, writes) = match spec.operands {
+        Operands::RdRsRt | Operands::RdRtRs => (rs | rt, rd),
+        Operands::RdRtShamt => (rt, rd),
+        // madd/msub accumulate into HI and LO
+        Operands::RsRt if spec.opcode == op::SPECIAL2 => (rs | rt | HI | LO, HI | LO),
+        Operands::RsRt if matches!(spec.funct, funct::TEQ | funct::TNE) => (rs | rt, 0),
+        Operands::RsRt => (rs | rt, HI | LO),
+        Operands::RdRs => (rs, rd),
+        Operands::Rd if spec.mnemonic == "mfhi" => (HI, rd),
+        Operands::Rd => (LO, rd),
+        Operands::Rs => match spec.mnemonic {
+            "mthi" => (rs, HI),
+            "mtlo" => (rs, LO),
+            _ => (rs, 0),
+        },
+        Operands::Jalr => (rs, rd),
+        // syscall takes its service in $v0, arguments in $a0-$a2 or $f12 and
+        // may return in $v0 or $f0
+        Operands::None if spec.mnemonic == "syscall" => (
+            bit(2) | bit(4) | bit(5) | bit(6) | fpr(12, true),
+            bit(2) | fpr(0, true),
+        ),
+        Operands::None => (0, 0),
+        Operands::RtRsSimm | Operands::RtRsImm => (rs, rt),
+        Operands::RtImm => (0, rt),
+        Operands::Memory if isa::is_store(spec.opcode) => (rs | rt, 0),
+        // lwl/lwr merge into the bytes of rt they don't load
+        Operands::Memory if matches!(spec.opcode, op::LWL | op::LWR) => (rs | rt, rt),
+        Operands::Memory => (rs, rt),
+        Operands::Branch2 => (rs | rt, 0),
+        Operands::Branch1 if spec.mnemonic.ends_with("al") => (rs, bit(31)),
+        Operands::Branch1 => (rs, 0),
+        Operands::Jump if spec.mnemonic == "jal" => (0, bit(31)),
+        Operands::Jump => (0, 0),
+        Operands::CopMove if matches!(spec.mnemonic, "mfc0" | "cfc1") => (0, rt),
+        Operands::CopMove if spec.mnemonic == "ctc1" => (rt, FCSR),
+        Operands::CopMove => (rt, 0),
+        Operands::FdFsFt => (fs | ft, fd),
+        Operands::FdFs => (fs, fd),
+        Operands::FsFt => (fs | ft, FCSR),
+        Operands::RtFs if spec.mnemonic == "mfc1" => (fpr(isa::rd(word), false), rt),
+        Operands::RtFs => (rt, fpr(isa::rd(word), false)),
+        Operands::FpMemory if isa::is_store(spec.opcode) => (rs | ft, 0),
+        Operands::FpMemory => (rs, ft),
+        Operands::Bc1 => (FCSR, 0),
+    };
+    (reads & !1, writes & !1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::memory::Endianness;
+    use crate::syscall::TestConsole;
+
+    #[test]
+    fn odd_double_stays_within_the_fprs() {
+        // add.d $f0, $f0, $f31
+        let (reads, writes) = register_usage(0x463F_0000);
+        let pair = |r: usize| 3u128 << (FPR_BASE + r);
+        assert_eq!(reads, pair(0) | pair(30));
+        assert_eq!(writes, pair(0));
+        assert_eq!((reads | writes) & FCSR, 0);
+    }
+
+    /// Stalls and flushes running `body` to its closing `exit`, with
+    /// padding so the syscall's `$v0` is ready well before it's needed
+    fn counts(body: &str, forwarding: bool) -> (u64, u64) {
+        let source = format!("li $v0, 10\nnop\nnop\n{body}\nnop\nnop\nsyscall\n");
+        let program = assemble(&source, Endianness::Big).expect("program assembles");
+        let mut machine = Machine::new();
+        machine.load_program(&program);
+        let mut pipeline = Pipeline {
+            forwarding,
+            ..Pipeline::default()
+        };
+        pipeline.reset(machine.cpu.pc);
+        let mut console = TestConsole::default();
+        for _ in 0..100 {
+            match pipeline.cycle(&mut machine, &mut console) {
+                Ok(Status::Running) => {}
+                Ok(Status::Exited(_)) => return (pipeline.stalls, pipeline.flushes),
+                other => panic!("{other:?}"),
+            }
+        }
+        panic!("program did not exit");
+    }
+
+    #[test]
+    fn independent_instructions_flow_freely() {
+        let body = "addu $t0, $t1, $t2\naddu $t3, $t4, $t5";
+        assert_eq!(counts(body, true), (0, 0));
+        assert_eq!(counts(body, false), (0, 0));
+    }
+
+    #[test]
+    fn alu_results_stall_only_without_forwarding() {
+        let body = "addu $t0, $t1, $t2\naddu $t3, $t0, $t0";
+        assert_eq!(counts(body, true), (0, 0));
+        assert_eq!(counts(body, false), (2, 0));
+        // One instruction between them leaves only the MEM conflict
+        let body = "addu $t0, $t1, $t2\nnop\naddu $t3, $t0, $t0";
+        assert_eq!(counts(body, false), (1, 0));
+    }
+
+    #[test]
+    fn load_use_stalls_even_with_forwarding() {
+        let body = "lw $t0, 0($sp)\naddu $t1, $t0, $t0";
+        assert_eq!(counts(body, true), (1, 0));
+        assert_eq!(counts(body, false), (2, 0));
+    }
+
+    #[test]
+    fn taken_branches_flush() {
+        let body = "beq $zero, $zero, skip\naddu $t0, $t1, $t2\nskip: addu $t3, $t4, $t5";
+        assert_eq!(counts(body, true), (0, 1));
+        let body = "bne $zero, $zero, skip\naddu $t0, $t1, $t2\nskip: addu $t3, $t4, $t5";
+        assert_eq!(counts(body, true), (0, 0));
+        // A jump to the next instruction costs nothing
+        assert_eq!(counts("j next\nnext: nop", true), (0, 0));
+    }
+}
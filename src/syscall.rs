@@ -0,0 +1,283 @@
+//! SPIM/MARS-compatible `syscall` services selected by `$v0`
+
+use std::collections::VecDeque;
+
+use crate::cpu::Cpu;
+use crate::memory::Memory;
+
+const V0: usize = 2;
+const A0: usize = 4;
+const A1: usize = 5;
+/// FPU registers for float and double arguments and results
+const F0: usize = 0;
+const F12: usize = 12;
+
+/// First address handed out by `sbrk`, as in MARS
+pub const HEAP_BASE: u32 = 0x1004_0000;
+
+/// Longest string `print_string` will look for a NUL in
+const MAX_STRING_LEN: u32 = 64 << 10;
+
+/// Where syscall output goes and input comes from
+pub trait Console {
+    fn print(&mut self, text: &str);
+
+    /// Take the next line of input without its newline, or `None` if no
+    /// input is available yet
+    fn read_line(&mut self) -> Option<String>;
+}
+
+/// What the machine should do after a syscall
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Continue,
+    Exit(i32),
+    /// The service needs input the console doesn't have yet; retry the syscall later
+    NeedsInput,
+}
+
+/// A syscall that could not be serviced
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyscallError {
+    UnknownService(u32),
+    InvalidInput(String),
+    /// No NUL within `MAX_STRING_LEN` bytes of the address
+    UnterminatedString(u32),
+}
+
+impl std::fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownService(code) => write!(f, "unknown syscall service {code} in $v0"),
+            Self::InvalidInput(text) => write!(f, "invalid input '{text}'"),
+            Self::UnterminatedString(address) => write!(
+                f,
+                "string at 0x{address:08X} has no NUL within {} KiB",
+                MAX_STRING_LEN >> 10
+            ),
+        }
+    }
+}
+
+/// Syscall state that outlives a single call: the `sbrk` heap break and
+/// the rest of a line `read_char` took a character from
+#[derive(Clone, Debug)]
+pub struct Syscalls {
+    heap_end: u32,
+    /// Input not yet read, ending with the line's newline when not empty
+    unread: VecDeque<u8>,
+}
+
+impl Default for Syscalls {
+    fn default() -> Self {
+        Self {
+            heap_end: HEAP_BASE,
+            unread: VecDeque::new(),
+        }
+    }
+}
+
+impl Syscalls {
+    /// Perform the service selected by `$v0`; PC is left on the `syscall`
+    pub fn handle(
+        &mut self,
+        cpu: &mut Cpu,
+        memory: &mut Memory,
+        console: &mut dyn Console,
+    ) -> Result<Outcome, SyscallError> {
+        let a0 = cpu.reg(A0);
+        match cpu.reg(V0) {
+            // print_int
+            1 => console.print(&(a0 as i32).to_string()),
+            // print_float
+            2 => console.print(&format!("{:?}", cpu.cp1.single(F12))),
+            // print_double
+            3 => console.print(&format!("{:?}", cpu.cp1.double(F12))),
+            // print_string
+            4 => console.print(&read_c_string(memory, a0)?),
+            // read_int
+            5 => {
+                let Some(line) = self.read_line(console) else {
+                    return Ok(Outcome::NeedsInput);
+                };
+                let value = line
+                    .trim()
+                    .parse::<i32>()
+                    .map_err(|_| SyscallError::InvalidInput(line.clone()))?;
+                cpu.set_reg(V0, value as u32);
+            }
+            // read_float
+            6 => {
+                let Some(line) = self.read_line(console) else {
+                    return Ok(Outcome::NeedsInput);
+                };
+                let value = line
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|_| SyscallError::InvalidInput(line.clone()))?;
+                cpu.set_fpr(F0, value.to_bits());
+            }
+            // read_double
+            7 => {
+                let Some(line) = self.read_line(console) else {
+                    return Ok(Outcome::NeedsInput);
+                };
+                let value = line
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| SyscallError::InvalidInput(line.clone()))?;
+                let bits = value.to_bits();
+                cpu.set_fpr(F0, bits as u32);
+                cpu.set_fpr(F0 + 1, (bits >> 32) as u32);
+            }
+            // read_string: like fgets, at most $a1 - 1 characters plus a NUL
+            8 => {
+                let Some(mut line) = self.read_line(console) else {
+                    return Ok(Outcome::NeedsInput);
+                };
+                line.push('\n');
+                let capacity = (cpu.reg(A1) as i32).max(1) as usize - 1;
+                let bytes = &line.as_bytes()[..line.len().min(capacity)];
+                for (i, &byte) in bytes.iter().enumerate() {
+                    memory.store_byte(a0.wrapping_add(i as u32), byte);
+                }
+                if cpu.reg(A1) as i32 > 0 {
+                    memory.store_byte(a0.wrapping_add(bytes.len() as u32), 0);
+                }
+            }
+            // sbrk
+            9 => {
+                cpu.set_reg(V0, self.heap_end);
+                let size = (a0 as i32).max(0) as u32;
+                self.heap_end = self.heap_end.wrapping_add(size.wrapping_add(3) & !3);
+            }
+            // exit
+            10 => return Ok(Outcome::Exit(0)),
+            // print_char
+            11 => console.print(&char::from(a0 as u8).to_string()),
+            // read_char: the rest of the line stays for the next read
+            12 => {
+                if self.unread.is_empty() {
+                    let Some(line) = console.read_line() else {
+                        return Ok(Outcome::NeedsInput);
+                    };
+                    self.unread.extend(line.bytes());
+                    self.unread.push_back(b'\n');
+                }
+                let c = self.unread.pop_front().unwrap_or(b'\n');
+                cpu.set_reg(V0, c as u32);
+            }
+            // exit2
+            17 => return Ok(Outcome::Exit(a0 as i32)),
+            // print_int_hex
+            34 => console.print(&format!("0x{a0:08x}")),
+            // print_int_binary
+            35 => console.print(&format!("{a0:032b}")),
+            // print_int_unsigned
+            36 => console.print(&a0.to_string()),
+            code => return Err(SyscallError::UnknownService(code)),
+        }
+        Ok(Outcome::Continue)
+    }
+
+    /// Next line of input without its newline: what `read_char` left of
+    /// the current line, or else a new line from the console
+    fn read_line(&mut self, console: &mut dyn Console) -> Option<String> {
+        if self.unread.is_empty() {
+            return console.read_line();
+        }
+        let mut bytes: Vec<u8> = self.unread.drain(..).collect();
+        bytes.pop();
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// Read a NUL-terminated string, one byte per character
+fn read_c_string(memory: &Memory, address: u32) -> Result<String, SyscallError> {
+    let mut text = String::new();
+    for offset in 0..MAX_STRING_LEN {
+        match memory.load_byte(address.wrapping_add(offset)) {
+            0 => return Ok(text),
+            byte => text.push(char::from(byte)),
+        }
+    }
+    Err(SyscallError::UnterminatedString(address))
+}
+
+/// Console with scripted input lines that records what is printed, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct TestConsole {
+    pub input: VecDeque<String>,
+    pub output: String,
+}
+
+#[cfg(test)]
+impl Console for TestConsole {
+    fn print(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(
+        syscalls: &mut Syscalls,
+        cpu: &mut Cpu,
+        memory: &mut Memory,
+        console: &mut TestConsole,
+        service: u32,
+    ) -> Result<Outcome, SyscallError> {
+        cpu.set_reg(V0, service);
+        syscalls.handle(cpu, memory, console)
+    }
+
+    #[test]
+    fn read_char_leaves_the_rest_of_the_line() {
+        let (mut syscalls, mut cpu, mut memory) =
+            (Syscalls::default(), Cpu::default(), Memory::new());
+        let mut console = TestConsole {
+            input: ["ab".to_owned(), "x42".to_owned()].into(),
+            ..TestConsole::default()
+        };
+        let mut read_char = |console: &mut TestConsole| {
+            call(&mut syscalls, &mut cpu, &mut memory, console, 12).unwrap();
+            cpu.reg(V0) as u8
+        };
+        assert_eq!(read_char(&mut console), b'a');
+        assert_eq!(read_char(&mut console), b'b');
+        assert_eq!(read_char(&mut console), b'\n');
+        assert_eq!(read_char(&mut console), b'x');
+        // read_int gets the rest of the line read_char started
+        call(&mut syscalls, &mut cpu, &mut memory, &mut console, 5).unwrap();
+        assert_eq!(cpu.reg(V0), 42);
+        assert_eq!(
+            call(&mut syscalls, &mut cpu, &mut memory, &mut console, 12),
+            Ok(Outcome::NeedsInput)
+        );
+    }
+
+    #[test]
+    fn print_string_needs_a_nul_within_the_limit() {
+        let (mut syscalls, mut cpu, mut memory) =
+            (Syscalls::default(), Cpu::default(), Memory::new());
+        let mut console = TestConsole::default();
+        for offset in (0..MAX_STRING_LEN).step_by(4) {
+            memory.write_word(0x1001_0000 + offset, 0x4141_4141);
+        }
+        cpu.set_reg(A0, 0x1001_0000);
+        assert_eq!(
+            call(&mut syscalls, &mut cpu, &mut memory, &mut console, 4),
+            Err(SyscallError::UnterminatedString(0x1001_0000))
+        );
+        cpu.set_reg(A0, 0x1001_0000 + MAX_STRING_LEN - 2);
+        call(&mut syscalls, &mut cpu, &mut memory, &mut console, 4).unwrap();
+        assert_eq!(console.output, "AA");
+    }
+}
@@ -0,0 +1,55 @@
+# Big-endian MIPS32 test program for the ELF loader, written the way GCC
+# emits code: filled delay slots, a trap after division and an unaligned
+# word copy. Prints "8 30 12345678" and exits.
+#
+# delay_slots.elf is this file assembled with
+# `llvm-mc -triple=mips -mcpu=mips32 -filetype=obj` and linked at 0x00400000
+# with `main` as the entry point.
+        .set    noreorder
+        .text
+        .globl  main
+main:
+        li      $t0, 7
+        li      $t1, 2
+        div     $zero, $t0, $t1
+        teq     $t1, $zero, 7
+        mflo    $a0
+        bal     twice
+        addiu   $a0, $a0, 1             # delay slot: twice(3 + 1)
+        li      $v0, 1
+        syscall
+        jal     space
+        nop
+
+        li      $t2, 3
+        move    $a0, $zero
+loop:
+        addiu   $t2, $t2, -1
+        bnez    $t2, loop
+        addiu   $a0, $a0, 10            # delay slot: runs every iteration
+        li      $v0, 1
+        syscall
+        bal     space
+        nop
+
+        li      $t0, 12345678
+        swl     $t0, 1($sp)
+        swr     $t0, 4($sp)
+        lwl     $a0, 1($sp)
+        lwr     $a0, 4($sp)
+        li      $v0, 1
+        syscall
+        li      $v0, 10
+        syscall
+
+twice:
+        jr      $ra
+        addu    $a0, $a0, $a0           # delay slot
+
+space:
+        move    $t3, $a0
+        li      $a0, 32
+        li      $v0, 11
+        syscall
+        jr      $ra
+        move    $a0, $t3